@@ -0,0 +1,65 @@
+// The bench only uses the control tables and the test module imports go unused here
+#[path = "../src/utils.rs"]
+#[allow(dead_code, unused_imports)]
+mod utils;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use tap::Pipe;
+use utils::*;
+
+// The original linear scan kept around as a baseline
+fn linear_at_or_after(table: &[P32], offset: P32) -> &[P32] {
+    table
+        .iter()
+        .take_while(|item| item.quantify() < offset)
+        .count()
+        .saturating_sub(1)
+        .pipe(|start| &table[start..])
+}
+
+fn playback(len: usize) -> (Vec<P32>, Vec<P32>) {
+    let table = (0..len).map(|i| p32(i as f32)).collect();
+    // Roughly one lookup per frame over the whole table
+    let offsets = (0..1000)
+        .map(|i| p32(i as f32 * len as f32 / 1000.))
+        .collect();
+    (table, offsets)
+}
+
+fn control_table(c: &mut Criterion) {
+    let mut group = c.benchmark_group("at_or_after");
+
+    [100, 1_000, 10_000, 100_000].into_iter().for_each(|len| {
+        let (table, offsets) = playback(len);
+
+        group.bench_with_input(BenchmarkId::new("linear", len), &len, |b, _| {
+            b.iter(|| {
+                offsets.iter().for_each(|offset| {
+                    black_box(linear_at_or_after(&table, *offset));
+                })
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("binary", len), &len, |b, _| {
+            b.iter(|| {
+                offsets.iter().for_each(|offset| {
+                    black_box(table.as_slice().at_or_after(*offset));
+                })
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("cursor", len), &len, |b, _| {
+            let cursor = Cursor::default();
+            b.iter(|| {
+                offsets.iter().for_each(|offset| {
+                    black_box(table.as_slice().seek(*offset, &cursor));
+                })
+            })
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, control_table);
+criterion_main!(benches);
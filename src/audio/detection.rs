@@ -0,0 +1,415 @@
+use super::{waveform::fft, Frame, SongInfo};
+use crate::{
+    timing::{Tempo, TempoMap},
+    utils::*,
+};
+use bevy::{prelude::*, tasks::*};
+use futures_lite::future;
+use tap::Pipe;
+
+use std::{f32::consts::TAU, sync::Arc};
+
+/// Frames transformed for each value of the onset envelope
+const ONSET_WINDOW: usize = 1024;
+
+/// Frames between values of the onset envelope
+const ONSET_HOP: usize = 256;
+
+/// Frames compared when placing an onset within its window
+const ONSET_BLOCK: usize = 32;
+
+/// Values either side of an onset which it must exceed
+const ONSET_SPREAD: usize = 4;
+
+/// Values either side of an onset which its threshold is averaged over
+const ONSET_CONTEXT: usize = 16;
+
+const MIN_BPM: f32 = 60.;
+const MAX_BPM: f32 = 200.;
+
+/// Tempo, first beat and onsets estimated from a song
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct TimingDetection {
+    /// Zero when no tempo could be found
+    pub bpm: P32,
+    pub first_beat: P32,
+    pub onsets: Vec<P32>,
+}
+
+#[derive(Resource, Default)]
+pub struct TimingDetectionTask(Option<Task<TimingDetection>>);
+
+impl TimingDetection {
+    pub fn analyse(frames: &[Frame], sample_rate: u32) -> Self {
+        let hop_length = ONSET_HOP as f32 / sample_rate as f32;
+        let envelope = onset_envelope(frames);
+
+        let onsets = pick_onsets(&envelope)
+            .map(|hop| place_onset(frames, hop) as f32 / sample_rate as f32)
+            .collect::<Vec<_>>();
+
+        let Some(period) = beat_period(&envelope, sample_rate) else {
+            return Self {
+                onsets: onsets.into_iter().map(p32).collect(),
+                ..default()
+            };
+        };
+
+        let phase = beat_phase(&envelope, period) as f32 * hop_length;
+        let (first_beat, beat_length) = fit_beats(&onsets, phase, period * hop_length);
+
+        Self {
+            bpm: p32(60. / beat_length),
+            first_beat: p32(first_beat.max(0.)),
+            onsets: onsets.into_iter().map(p32).collect(),
+        }
+    }
+
+    /// Single tempo in chart time starting at the first beat which isn't before the chart
+    pub fn tempo_map(&self, meter: u8, song_info: &SongInfo) -> TempoMap {
+        if self.bpm.raw() <= f32::EPSILON {
+            return TempoMap::default();
+        }
+
+        let beat_length = 60. / self.bpm.raw();
+        let skipped = ((song_info.offset.raw() - self.first_beat.raw()) / beat_length)
+            .ceil()
+            .max(0.);
+
+        TempoMap(vec![Tempo {
+            offset: song_info.chart_pos((self.first_beat.raw() + skipped * beat_length).into()),
+            bpm: self.bpm,
+            meter,
+        }])
+    }
+
+    /// Onsets in chart time. Onsets before the chart are dropped.
+    pub fn chart_onsets<'a>(&'a self, song_info: &'a SongInfo) -> impl Iterator<Item = P32> + 'a {
+        self.onsets
+            .iter()
+            .filter(|onset| song_info.offset.raw() <= onset.raw())
+            .map(|onset| song_info.chart_pos(onset.raw().into()))
+    }
+}
+
+/// Detects the timing of the song on the async compute pool
+pub fn detect_timing(task: &mut TimingDetectionTask, frames: Arc<Vec<Frame>>, rate: u32) {
+    task.0 = AsyncComputeTaskPool::get()
+        .spawn(async move { TimingDetection::analyse(&frames, rate) })
+        .pipe(Some);
+}
+
+pub fn receive_timing_detection(
+    mut task: ResMut<TimingDetectionTask>,
+    mut detection: ResMut<TimingDetection>,
+) {
+    if task.0.as_ref().is_some_and(|task| task.is_finished()) {
+        *detection = task.0.take().map(future::block_on).unwrap();
+    }
+}
+
+fn mono(frame: &Frame) -> f32 {
+    (frame.left + frame.right) * 0.5
+}
+
+/// Spectral flux of log compressed magnitudes
+fn onset_envelope(frames: &[Frame]) -> Vec<f32> {
+    let window = (0..ONSET_WINDOW)
+        .map(|n| 0.5 - 0.5 * (TAU * n as f32 / ONSET_WINDOW as f32).cos())
+        .collect::<Vec<_>>();
+
+    let mut previous = vec![0.; ONSET_WINDOW / 2];
+
+    (0..frames.len() / ONSET_HOP)
+        .map(|hop| {
+            let mut bins = (0..ONSET_WINDOW)
+                .map(|n| frames.get(hop * ONSET_HOP + n).map_or(0., mono) * window[n])
+                .map(|sample| (sample, 0.))
+                .collect::<Vec<_>>();
+
+            fft(&mut bins);
+
+            let magnitudes = bins[..ONSET_WINDOW / 2]
+                .iter()
+                .map(|(re, im)| (1. + 100. * (re * re + im * im).sqrt()).ln())
+                .collect::<Vec<_>>();
+
+            let flux = magnitudes
+                .iter()
+                .zip(&previous)
+                .map(|(magnitude, previous)| (magnitude - previous).max(0.))
+                .sum();
+
+            previous = magnitudes;
+            flux
+        })
+        .collect()
+}
+
+/// Local maxima of the envelope above an adaptive threshold
+fn pick_onsets(envelope: &[f32]) -> impl Iterator<Item = usize> + '_ {
+    let floor = envelope.iter().copied().fold(0., f32::max) * 0.1;
+    let around = move |hop: usize, radius: usize| {
+        &envelope[hop.saturating_sub(radius)..(hop + radius + 1).min(envelope.len())]
+    };
+
+    (0..envelope.len()).filter(move |&hop| {
+        let value = envelope[hop];
+        let context = around(hop, ONSET_CONTEXT);
+        let threshold = floor + 1.5 * context.iter().sum::<f32>() / context.len() as f32;
+
+        threshold < value
+            && around(hop, ONSET_SPREAD)
+                .iter()
+                .all(|other| *other <= value)
+            && envelope[hop.saturating_sub(ONSET_SPREAD)..hop]
+                .iter()
+                .all(|other| *other < value)
+    })
+}
+
+/// Frame within the window of the hop where the energy rises the most
+fn place_onset(frames: &[Frame], hop: usize) -> usize {
+    let start = hop * ONSET_HOP;
+    let energy = |block: usize| {
+        frames
+            .iter()
+            .skip(start + block * ONSET_BLOCK)
+            .take(ONSET_BLOCK)
+            .map(|frame| mono(frame).powi(2))
+            .sum::<f32>()
+    };
+
+    (1..ONSET_WINDOW / ONSET_BLOCK)
+        .map(|block| (block, energy(block) - energy(block - 1)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(start, |(block, _)| start + block * ONSET_BLOCK)
+}
+
+/// Hops per beat from the autocorrelation of the envelope, weighted towards 120 BPM
+fn beat_period(envelope: &[f32], sample_rate: u32) -> Option<f32> {
+    let hops_per_minute = 60. * sample_rate as f32 / ONSET_HOP as f32;
+    let mean = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+    let centred = envelope
+        .iter()
+        .map(|value| value - mean)
+        .collect::<Vec<_>>();
+
+    let correlation = |lag: usize| {
+        centred
+            .iter()
+            .zip(&centred[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            / (centred.len() - lag) as f32
+    };
+
+    let lags = (hops_per_minute / MAX_BPM) as usize
+        ..=((hops_per_minute / MIN_BPM) as usize).min(centred.len().saturating_sub(2));
+
+    let lag = lags
+        .map(|lag| {
+            let octaves = (hops_per_minute / lag as f32 / 120.).log2();
+            (lag, correlation(lag) * (-0.5 * octaves.powi(2)).exp())
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .filter(|(_, score)| 0. < *score)
+        .map(|(lag, _)| lag)?;
+
+    // Beats which fall halfway between every detected beat mean the tempo is double
+    let lag = std::iter::successors(Some(lag), |lag| {
+        let half = (lag / 2 - 1..=lag / 2 + 1)
+            .max_by(|a, b| correlation(*a).total_cmp(&correlation(*b)))?;
+        (hops_per_minute / MAX_BPM <= half as f32 && correlation(*lag) * 0.8 <= correlation(half))
+            .then_some(half)
+    })
+    .last()
+    .unwrap_or(lag);
+
+    let [before, peak, after] = [lag - 1, lag, lag + 1].map(correlation);
+    let curvature = before - 2. * peak + after;
+
+    match f32::EPSILON < curvature.abs() {
+        true => lag as f32 + 0.5 * (before - after) / curvature,
+        false => lag as f32,
+    }
+    .pipe(Some)
+}
+
+/// Hop of the first beat of the comb which collects the most flux
+fn beat_phase(envelope: &[f32], period: f32) -> usize {
+    (0..period.ceil() as usize)
+        .map(|phase| {
+            let flux = (0..)
+                .map(|beat| (phase as f32 + beat as f32 * period).round() as usize)
+                .take_while(|hop| *hop < envelope.len())
+                .map(|hop| envelope[hop])
+                .sum::<f32>();
+            (phase, flux)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(phase, _)| phase)
+}
+
+/// Least squares fit of the onsets on the beat grid. Returns the first beat and beat length.
+fn fit_beats(onsets: &[f32], phase: f32, beat_length: f32) -> (f32, f32) {
+    let matched = onsets
+        .iter()
+        .filter_map(|onset| {
+            let beat = ((onset - phase) / beat_length).round();
+            ((onset - phase - beat * beat_length).abs() < beat_length * 0.2)
+                .then_some((beat, *onset))
+        })
+        .collect::<Vec<_>>();
+
+    let count = matched.len() as f32;
+    let (sum_beat, sum_onset) = matched
+        .iter()
+        .fold((0., 0.), |(beats, onsets), (beat, onset)| {
+            (beats + beat, onsets + onset)
+        });
+    let (sum_squares, sum_products) = matched
+        .iter()
+        .fold((0., 0.), |(squares, products), (beat, onset)| {
+            (squares + beat * beat, products + beat * onset)
+        });
+
+    let denominator = count * sum_squares - sum_beat * sum_beat;
+    if matched.len() < 2 || denominator.abs() < f32::EPSILON {
+        return (phase, beat_length);
+    }
+
+    let fitted_length = (count * sum_products - sum_beat * sum_onset) / denominator;
+    let intercept = (sum_onset - fitted_length * sum_beat) / count;
+    let first = matched
+        .iter()
+        .map(|(beat, _)| *beat)
+        .fold(f32::INFINITY, f32::min);
+
+    (intercept + first * fitted_length, fitted_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_kira_audio::prelude::{StaticSoundData, StaticSoundSettings};
+    use noisy_float::prelude::r32;
+    use std::{fs, path::Path};
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Mono 16 bit WAV with a short decaying click on every beat
+    fn write_click_track(path: &Path, bpm: f32, first_beat: f32, seconds: f32) {
+        let beat_length = 60. / bpm;
+        let samples = (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|frame| frame as f32 / SAMPLE_RATE as f32)
+            .map(|time| match time < first_beat {
+                true => 0.,
+                false => (time - first_beat) % beat_length,
+            })
+            .map(|since| (since * 1500. * TAU).sin() * (-since * 200.).exp())
+            .map(|sample| (sample * 0.8 * i16::MAX as f32) as i16)
+            .collect::<Vec<_>>();
+
+        let data = samples.len() as u32 * 2;
+        let bytes = [
+            b"RIFF".as_slice(),
+            &(36 + data).to_le_bytes(),
+            b"WAVEfmt ",
+            &16u32.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &SAMPLE_RATE.to_le_bytes(),
+            &(SAMPLE_RATE * 2).to_le_bytes(),
+            &2u16.to_le_bytes(),
+            &16u16.to_le_bytes(),
+            b"data",
+            &data.to_le_bytes(),
+        ]
+        .concat()
+        .into_iter()
+        .chain(samples.iter().flat_map(|sample| sample.to_le_bytes()))
+        .collect::<Vec<_>>();
+
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn detect(bpm: f32, first_beat: f32) -> TimingDetection {
+        let dir = std::env::temp_dir().join(format!("click-track-{}", std::process::id()));
+        let path = dir.join(format!("{bpm}-{first_beat}.wav"));
+        fs::create_dir_all(&dir).unwrap();
+        write_click_track(&path, bpm, first_beat, 12.);
+
+        let sound = StaticSoundData::from_file(&path, StaticSoundSettings::default()).unwrap();
+        fs::remove_file(&path).unwrap();
+        TimingDetection::analyse(&sound.frames, sound.sample_rate)
+    }
+
+    #[test]
+    fn detect_click_tracks() {
+        for (bpm, first_beat) in [(120., 0.5), (93., 0.31), (174., 1.2)] {
+            let detection = detect(bpm, first_beat);
+            let beat_length = 60. / bpm;
+            let beats = ((12. - first_beat) / beat_length).ceil() as usize;
+
+            assert!(
+                (detection.bpm.raw() - bpm).abs() < 0.1,
+                "{bpm}: {detection:?}"
+            );
+            assert!(
+                (detection.first_beat.raw() - first_beat).abs() < 0.005,
+                "{detection:?}"
+            );
+            assert_eq!(
+                detection.onsets.len(),
+                beats,
+                "{bpm}: {:?}",
+                detection.onsets
+            );
+            assert!(detection
+                .onsets
+                .iter()
+                .enumerate()
+                .all(
+                    |(beat, onset)| (onset.raw() - first_beat - beat as f32 * beat_length).abs()
+                        < 0.005
+                ));
+        }
+    }
+
+    #[test]
+    fn detect_silence() {
+        let detection =
+            TimingDetection::analyse(&vec![Frame::ZERO; SAMPLE_RATE as usize * 4], SAMPLE_RATE);
+
+        assert_eq!(detection, TimingDetection::default());
+        assert_eq!(
+            detection.tempo_map(4, &SongInfo::default()),
+            TempoMap::default()
+        );
+    }
+
+    #[test]
+    fn chart_time_suggestions() {
+        let detection = TimingDetection {
+            bpm: p32(120.),
+            first_beat: p32(0.5),
+            onsets: [0.5, 1., 1.5].map(p32).to_vec(),
+        };
+        let offset = |offset| SongInfo {
+            offset: r32(offset),
+            ..default()
+        };
+        let first_beat = |offset| detection.tempo_map(4, &offset)[0].offset;
+
+        assert_eq!(first_beat(offset(0.)), p32(0.5));
+        assert!((first_beat(offset(0.2)).raw() - 0.3).abs() < 0.0001);
+        assert!((first_beat(offset(0.7)).raw() - 0.3).abs() < 0.0001);
+        assert!((first_beat(offset(-0.25)).raw() - 0.75).abs() < 0.0001);
+
+        assert_eq!(
+            detection.chart_onsets(&offset(1.)).collect::<Vec<_>>(),
+            [0., 0.5].map(p32)
+        );
+    }
+}
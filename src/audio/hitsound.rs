@@ -0,0 +1,120 @@
+use super::{
+    AudioChannel, AudioControl, KiraSource, PlaybackState, SongChannel, SongInfo, SongLoadEvent,
+};
+use crate::{hit::*, utils::*, GameState, Settings};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_kira_audio::prelude::{StaticSoundData, StaticSoundSettings};
+
+use std::fs;
+
+/// Directory of the chart which holds its hitsound samples
+pub const HITSOUND_DIR: &str = "hitsounds";
+
+#[derive(Resource, Default)]
+pub struct HitsoundChannel;
+
+/// Samples of the chart by file name
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct Hitsounds(HashMap<String, Handle<KiraSource>>);
+
+impl Hitsounds {
+    /// The volume of the hitsound is scaled by the master volume since per sound volumes
+    /// replace the volume of the channel
+    fn play(&self, channel: &AudioChannel<HitsoundChannel>, hitsound: &Hitsound, volume: f32) {
+        match self.get(&hitsound.sample) {
+            Some(sample) => {
+                channel
+                    .play(sample.clone())
+                    .with_volume((hitsound.volume.raw() * volume) as f64);
+            }
+            None => warn!("Missing hitsound sample {}", hitsound.sample),
+        }
+    }
+}
+
+/// Plays the hitsounds of prompts as the song passes them in Edit
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct HitsoundPreview(pub bool);
+
+/// Samples are loaded from the hitsounds directory next to the song of the chart
+pub fn load_hitsounds(
+    mut song_load_events: EventReader<SongLoadEvent>,
+    mut kira_sources: ResMut<Assets<KiraSource>>,
+    mut hitsounds: ResMut<Hitsounds>,
+) {
+    let Some(SongLoadEvent { path, .. }) = song_load_events.iter().last() else {
+        return;
+    };
+
+    hitsounds.clear();
+
+    let Ok(entries) = fs::read_dir(path.with_file_name(HITSOUND_DIR)) else {
+        return;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .for_each(|path| {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+
+            match StaticSoundData::from_file(&path, StaticSoundSettings::default()) {
+                Ok(sound) => {
+                    hitsounds.insert(name, kira_sources.add(KiraSource { sound }));
+                }
+                Err(error) => warn!("Could not load hitsound {}: {error}", path.display()),
+            }
+        });
+}
+
+pub fn play_judged_hitsounds(
+    settings: Res<Settings>,
+    hitsounds: Res<Hitsounds>,
+    hitsound_channel: Res<AudioChannel<HitsoundChannel>>,
+    prompts: Query<&HitPrompt>,
+    mut judged_events: EventReader<PromptJudged>,
+) {
+    judged_events
+        .iter()
+        .flat_map(|PromptJudged(entity)| prompts.get(*entity).ok())
+        .flat_map(|prompt| prompt.hitsound.as_ref())
+        .for_each(|hitsound| hitsounds.play(&hitsound_channel, hitsound, settings.volume));
+}
+
+/// [`SongInfo::pos`] already has the audio offsets applied so previews line up with the song
+/// as it's heard. Prompts passed while seeking aren't previewed.
+pub fn preview_hitsounds(
+    state: Res<State<GameState>>,
+    preview: Res<HitsoundPreview>,
+    settings: Res<Settings>,
+    song_info: Res<SongInfo>,
+    hitsounds: Res<Hitsounds>,
+    song_channel: Res<AudioChannel<SongChannel>>,
+    hitsound_channel: Res<AudioChannel<HitsoundChannel>>,
+    prompts: Query<&HitPrompt>,
+    mut last_pos: Local<P32>,
+) {
+    let is_playing = matches!(
+        song_channel.state(&song_info.handle),
+        PlaybackState::Playing { .. }
+    );
+    let passed = *last_pos..song_info.pos;
+
+    if **preview
+        && is_playing
+        && matches!(state.0, GameState::Edit)
+        && song_info.pos - *last_pos < p32(0.25)
+    {
+        prompts
+            .iter()
+            .filter(|prompt| passed.contains(&prompt.offsets.start))
+            .flat_map(|prompt| prompt.hitsound.as_ref())
+            .for_each(|hitsound| hitsounds.play(&hitsound_channel, hitsound, settings.volume));
+    }
+
+    *last_pos = song_info.pos;
+}
@@ -1,20 +1,58 @@
-use crate::{utils::*, GameState};
-use bevy::{asset::FileAssetIo, prelude::*};
+mod detection;
+mod hitsound;
+mod waveform;
+
+pub use detection::*;
+pub use hitsound::*;
+pub use waveform::*;
+
+use crate::{timing::TempoMap, utils::*, GameState, Settings};
+use bevy::{asset::HandleId, prelude::*};
 pub use bevy_kira_audio::prelude::{
     AudioInstance as KiraInstance, AudioPlugin as KiraPlugin, AudioSource as KiraSource, *,
 };
-use noisy_float::prelude::R64;
-use tap::{Pipe, Tap};
+use noisy_float::prelude::*;
+use tap::Pipe;
+
+use std::{f32::consts::TAU, path::PathBuf, sync::Arc};
 
 #[derive(Resource, Default)]
 pub struct SongChannel;
 
+#[derive(Resource, Default)]
+pub struct MetronomeChannel;
+
 #[derive(Resource, Default, Debug)]
 pub struct SongInfo {
     pub pos: P32,
     pub dur: P32,
     pub title: String,
     pub handle: Handle<KiraInstance>,
+    pub source: Handle<KiraSource>,
+    /// Whether the song was played looped
+    pub looped: bool,
+    /// Seconds. Global and chart audio offsets combined.
+    pub offset: R32,
+    pub chart_offset: R32,
+    /// Seconds the visuals are drawn ahead of the audio
+    pub visual_offset: R32,
+}
+
+impl SongInfo {
+    /// Position in the audio of a position in the chart
+    pub fn audio_pos(&self, pos: f64) -> f64 {
+        pos + self.offset.raw() as f64
+    }
+
+    /// Position in the chart of a position in the audio
+    pub fn chart_pos(&self, audio_pos: f64) -> P32 {
+        p32((audio_pos as f32 - self.offset.raw()).max(0.))
+    }
+
+    /// Position in the chart which is drawn
+    pub fn visual_pos(&self) -> P32 {
+        p32((self.pos.raw() + self.visual_offset.raw()).max(0.))
+    }
 }
 
 #[derive(Default, Debug)]
@@ -23,54 +61,295 @@ pub struct ChartLoadEvent {
     pub start_from: R64,
 }
 
-fn load_chart_song(
+/// Sent once the chart of a [`ChartLoadEvent`] is loaded
+#[derive(Default, Debug)]
+pub struct SongLoadEvent {
+    pub path: PathBuf,
+    pub title: String,
+    pub start_from: R64,
+    /// Seconds
+    pub chart_offset: R32,
+}
+
+/// Sent in Play once the song of the chart has finished playing
+#[derive(Default, Debug)]
+pub struct ChartFinishedEvent;
+
+/// Region of the song which is looped in Edit. The whole song is looped without a region.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion(pub Option<(P32, P32)>);
+
+/// Playback speed of the song. [`SongInfo::pos`] stays in chart time at any rate.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Deref)]
+pub struct PlaybackRate(f64);
+
+impl PlaybackRate {
+    pub const MIN: f64 = 0.25;
+    pub const MAX: f64 = 2.;
+
+    pub fn new(rate: f64) -> Self {
+        Self(rate.clamp(Self::MIN, Self::MAX))
+    }
+}
+
+impl Default for PlaybackRate {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Clicks on every beat of the [`TempoMap`] while the song plays
+#[derive(Resource)]
+pub struct Metronome {
+    pub enabled: bool,
+    beat: Handle<KiraSource>,
+    downbeat: Handle<KiraSource>,
+}
+
+impl FromWorld for Metronome {
+    fn from_world(world: &mut World) -> Self {
+        let mut kira_sources = world.resource_mut::<Assets<KiraSource>>();
+
+        Self {
+            enabled: false,
+            beat: kira_sources.add(click(880.)),
+            downbeat: kira_sources.add(click(1320.)),
+        }
+    }
+}
+
+const CLICK_SAMPLE_RATE: u32 = 44100;
+
+/// Short decaying sine
+fn click_frames(frequency: f32) -> impl Iterator<Item = Frame> {
+    (0..CLICK_SAMPLE_RATE / 20)
+        .map(|frame| frame as f32 / CLICK_SAMPLE_RATE as f32)
+        .map(move |time| (time * frequency * TAU).sin() * (-time * 80.).exp() * 0.5)
+        .map(Frame::from_mono)
+}
+
+fn click_source(frames: Vec<Frame>) -> KiraSource {
+    KiraSource {
+        sound: StaticSoundData {
+            sample_rate: CLICK_SAMPLE_RATE,
+            frames: Arc::new(frames),
+            settings: default(),
+        },
+    }
+}
+
+fn click(frequency: f32) -> KiraSource {
+    click_frames(frequency).collect::<Vec<_>>().pipe(click_source)
+}
+
+/// Clicks from the start on every beat, accenting the first beat of every 4
+pub fn click_track(bpm: f32, beats: usize) -> KiraSource {
+    let beat_length = (60. / bpm * CLICK_SAMPLE_RATE as f32) as usize;
+    let mut frames = vec![Frame::ZERO; beats * beat_length];
+
+    (0..beats).for_each(|beat| {
+        let frequency = match beat % 4 {
+            0 => 1320.,
+            _ => 880.,
+        };
+
+        frames[beat * beat_length..]
+            .iter_mut()
+            .zip(click_frames(frequency))
+            .for_each(|(frame, click)| *frame = click);
+    });
+
+    click_source(frames)
+}
+
+/// Seconds before the end of the song which count as having played it through
+const FINISH_TOLERANCE: f32 = 0.25;
+
+/// Songs are looped in Edit and played once in Play
+fn is_looped(state: &GameState) -> bool {
+    matches!(state, GameState::Edit)
+}
+
+fn play_song(
+    song_channel: &AudioChannel<SongChannel>,
+    song_info: &SongInfo,
+    rate: &PlaybackRate,
+) -> Handle<KiraInstance> {
+    song_channel
+        .play(song_info.source.clone())
+        .start_from(song_info.audio_pos(song_info.pos.raw().into()).max(0.))
+        .with_playback_rate(rate.0)
+        .pipe(|command| match song_info.looped {
+            true => command.looped(),
+            false => command,
+        })
+        .handle()
+}
+
+fn load_song(
     state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    rate: Res<PlaybackRate>,
     song_channel: Res<AudioChannel<SongChannel>>,
-    mut chart_load_events: EventReader<ChartLoadEvent>,
+    mut song_load_events: EventReader<SongLoadEvent>,
     mut kira_sources: ResMut<Assets<KiraSource>>,
+    mut waveform_task: ResMut<WaveformTask>,
+    mut detection_task: ResMut<TimingDetectionTask>,
     mut song_info: ResMut<SongInfo>,
 ) {
-    let Some(ChartLoadEvent { chart_id, start_from }) = chart_load_events
+    let Some(SongLoadEvent { path, title, start_from, chart_offset }) = song_load_events
         .iter()
         .last()
     else {
         return
     };
 
-    let Ok(source) = FileAssetIo::get_base_path()
-        .tap_mut(|path| path.push("assets"))
-        .tap_mut(|path| path.push("charts"))
-        .tap_mut(|path| path.push(chart_id))
-        .tap_mut(|path| path.push("song.ogg"))
-        .pipe(|path| StaticSoundData::from_file(path, StaticSoundSettings::default()))
+    let Ok(source) = StaticSoundData::from_file(path, StaticSoundSettings::default())
         .map(|sound| KiraSource { sound })
     else {
-        error!("Could not load audio file");
+        error!("Could not load audio file {}", path.display());
         return;
     };
 
+    analyse_song(
+        &mut waveform_task,
+        path.clone(),
+        source.sound.frames.clone(),
+        source.sound.sample_rate,
+    );
+    detect_timing(
+        &mut detection_task,
+        source.sound.frames.clone(),
+        source.sound.sample_rate,
+    );
+
     *song_info = SongInfo {
         dur: source.sound.duration().as_secs_f32().pipe(p32),
         pos: p32(start_from.raw() as f32),
-        title: chart_id.to_string(),
-        handle: song_channel
-            .play(kira_sources.add(source))
-            .start_from(start_from.raw())
-            .looped()
-            .handle(),
+        title: title.clone(),
+        offset: *chart_offset + settings.audio_offset / 1000.,
+        chart_offset: *chart_offset,
+        source: kira_sources.add(source),
+        looped: is_looped(&state.0),
+        ..default()
     };
 
+    song_info.handle = play_song(&song_channel, &song_info, &rate);
+
     if matches!(state.0, GameState::Edit) {
         #[cfg(not(debug_assertions))]
         song_channel.pause();
     }
 }
 
-pub fn update_playback(mut song_info: ResMut<SongInfo>, instances: Res<Assets<KiraInstance>>) {
+/// Instances can't change whether they loop so the song is played again from where it is
+fn apply_loop_mode(
+    state: Res<State<GameState>>,
+    rate: Res<PlaybackRate>,
+    song_channel: Res<AudioChannel<SongChannel>>,
+    mut song_info: ResMut<SongInfo>,
+    mut instances: ResMut<Assets<KiraInstance>>,
+) {
+    let looped = is_looped(&state.0);
+
+    if song_info.looped == looped {
+        return;
+    }
+
+    let Some(instance) = instances.get_mut(&song_info.handle) else {
+        return;
+    };
+
+    instance.stop(AudioTween::default());
+    song_info.looped = looped;
+    song_info.handle = play_song(&song_channel, &song_info, &rate);
+}
+
+pub fn update_playback(
+    settings: Res<Settings>,
+    mut song_info: ResMut<SongInfo>,
+    instances: Res<Assets<KiraInstance>>,
+) {
+    song_info.offset = song_info.chart_offset + settings.audio_offset / 1000.;
+    song_info.visual_offset = r32(settings.visual_offset / 1000.);
     song_info.pos = instances
         .get(&song_info.handle)
         .and_then(|instance| instance.state().position())
-        .map_or(song_info.pos, |pos| p32(pos as f32))
+        .map_or(song_info.pos, |pos| song_info.chart_pos(pos))
+}
+
+pub fn loop_region(
+    state: Res<State<GameState>>,
+    region: Res<LoopRegion>,
+    song_info: Res<SongInfo>,
+    mut instances: ResMut<Assets<KiraInstance>>,
+) {
+    let Some((start, end)) = region.0 else {
+        return;
+    };
+
+    if matches!(state.0, GameState::Edit) && end <= song_info.pos {
+        if let Some(instance) = instances.get_mut(&song_info.handle) {
+            instance.seek_to(song_info.audio_pos(start.raw().into()));
+        }
+    }
+}
+
+fn apply_playback_rate(rate: Res<PlaybackRate>, song_channel: Res<AudioChannel<SongChannel>>) {
+    if rate.is_changed() {
+        song_channel.set_playback_rate(rate.0);
+    }
+}
+
+/// Only the latest beat clicks when several are passed within a frame
+fn tick_metronome(
+    metronome: Res<Metronome>,
+    tempo_map: Res<TempoMap>,
+    song_info: Res<SongInfo>,
+    song_channel: Res<AudioChannel<SongChannel>>,
+    metronome_channel: Res<AudioChannel<MetronomeChannel>>,
+    mut last_pos: Local<P32>,
+) {
+    let is_playing = matches!(
+        song_channel.state(&song_info.handle),
+        PlaybackState::Playing { .. }
+    );
+
+    if metronome.enabled && is_playing && *last_pos < song_info.pos {
+        if let Some(beat) = tempo_map.beats(*last_pos, song_info.pos).last() {
+            match tempo_map.is_downbeat(beat) {
+                true => metronome_channel.play(metronome.downbeat.clone()),
+                false => metronome_channel.play(metronome.beat.clone()),
+            };
+        }
+    }
+
+    *last_pos = song_info.pos;
+}
+
+/// Songs stop once they are played through in Play. Songs which are stopped before their
+/// end don't finish the chart.
+pub fn finish_chart(
+    state: Res<State<GameState>>,
+    song_info: Res<SongInfo>,
+    instances: Res<Assets<KiraInstance>>,
+    mut playing: Local<Option<(HandleId, f64)>>,
+    mut chart_finished_events: EventWriter<ChartFinishedEvent>,
+) {
+    let handle = song_info.handle.id();
+    let position = instances
+        .get(&song_info.handle)
+        .and_then(|instance| instance.state().position());
+
+    let played_through = playing.is_some_and(|(playing, last_position)| {
+        playing == handle && song_info.dur.raw() <= last_position as f32 + FINISH_TOLERANCE
+    });
+
+    if position.is_none() && played_through && matches!(state.0, GameState::Play) {
+        chart_finished_events.send(ChartFinishedEvent);
+    }
+
+    *playing = position.map(|position| (handle, position));
 }
 
 pub struct AudioPlugin;
@@ -80,8 +359,74 @@ impl Plugin for AudioPlugin {
         game.add_plugin(KiraPlugin)
             .init_resource::<SongInfo>()
             .add_audio_channel::<SongChannel>()
+            .add_audio_channel::<MetronomeChannel>()
+            .add_audio_channel::<HitsoundChannel>()
+            .init_resource::<Hitsounds>()
+            .init_resource::<HitsoundPreview>()
+            .init_resource::<PlaybackRate>()
+            .init_resource::<Metronome>()
+            .init_resource::<Waveform>()
+            .init_resource::<WaveformTask>()
+            .init_resource::<TimingDetection>()
+            .init_resource::<TimingDetectionTask>()
             .add_event::<ChartLoadEvent>()
+            .init_resource::<LoopRegion>()
+            .add_event::<SongLoadEvent>()
+            .add_event::<ChartFinishedEvent>()
             .add_system(update_playback)
-            .add_system(load_chart_song);
+            .add_systems(
+                (loop_region, finish_chart, tick_metronome, preview_hitsounds).after(update_playback),
+            )
+            .add_systems((load_hitsounds, play_judged_hitsounds))
+            .add_systems((
+                load_song,
+                apply_loop_mode,
+                apply_playback_rate,
+                receive_waveform,
+                receive_timing_detection,
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn audio_offsets() {
+        let mut game = App::new();
+        game.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<KiraInstance>()
+            .add_system(update_playback);
+
+        game.insert_resource(Settings {
+            audio_offset: 20.,
+            visual_offset: -10.,
+            ..default()
+        })
+        .insert_resource(SongInfo {
+            pos: p32(1.),
+            chart_offset: r32(0.03),
+            ..default()
+        });
+
+        game.update();
+
+        let song_info = game.world.resource::<SongInfo>();
+        assert!((song_info.offset.raw() - 0.05).abs() < f32::EPSILON);
+        assert!((song_info.audio_pos(1.) - 1.05).abs() < 0.0001);
+        assert!((song_info.chart_pos(1.05).raw() - 1.).abs() < 0.0001);
+        assert_eq!(song_info.chart_pos(0.01), p32(0.));
+        assert!((song_info.visual_pos().raw() - 0.99).abs() < 0.0001);
+    }
+
+    #[test]
+    fn playback_rates() {
+        assert_eq!(*PlaybackRate::default(), 1.);
+        assert_eq!(*PlaybackRate::new(0.1), PlaybackRate::MIN);
+        assert_eq!(*PlaybackRate::new(0.5), 0.5);
+        assert_eq!(*PlaybackRate::new(4.), PlaybackRate::MAX);
     }
 }
@@ -0,0 +1,353 @@
+use super::Frame;
+use bevy::{prelude::*, tasks::*};
+use futures_lite::future;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    f32::consts::{PI, TAU},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tap::Pipe;
+
+/// Cache of the analysis, stored next to the chart
+pub const WAVEFORM_FILE: &str = "waveform.bin";
+
+/// Frames summarised by each peak of the finest level
+pub const PEAK_WINDOW: usize = 256;
+
+/// Frames transformed for each column of the spectrogram
+pub const SPECTROGRAM_WINDOW: usize = 2048;
+
+/// Frequency bands of each column of the spectrogram
+pub const SPECTROGRAM_BANDS: usize = 96;
+
+/// Lowest level shown in the spectrogram
+const SPECTROGRAM_FLOOR_DB: f32 = -80.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Peak {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// Multi-resolution peaks and spectrogram of a song
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+    pub sample_rate: u32,
+    pub frames: usize,
+    /// Each level halves the resolution of the previous, starting at [`PEAK_WINDOW`] frames
+    pub levels: Vec<Vec<Peak>>,
+    /// Columns of [`SPECTROGRAM_BANDS`] log spaced bands, scaled to `0..=255`
+    pub spectrogram: Vec<Vec<u8>>,
+    /// RMS of the frames of each column of the spectrogram
+    pub loudness: Vec<f32>,
+}
+
+#[derive(Resource, Default)]
+pub struct WaveformTask(Option<Task<Waveform>>);
+
+impl Waveform {
+    pub fn analyse(frames: &[Frame], sample_rate: u32) -> Self {
+        let mono = frames
+            .iter()
+            .map(|frame| (frame.left + frame.right) * 0.5)
+            .collect::<Vec<_>>();
+
+        let finest = mono
+            .chunks(PEAK_WINDOW)
+            .map(|chunk| Peak {
+                min: chunk.iter().copied().fold(0., f32::min),
+                max: chunk.iter().copied().fold(0., f32::max),
+            })
+            .collect::<Vec<_>>();
+
+        let levels = std::iter::successors(Some(finest), |level| {
+            (1 < level.len()).then(|| {
+                level
+                    .chunks(2)
+                    .map(|pair| pair.iter().copied().reduce(Peak::merge).unwrap())
+                    .collect()
+            })
+        })
+        .collect();
+
+        Self {
+            sample_rate,
+            frames: frames.len(),
+            levels,
+            spectrogram: spectrogram(&mono),
+            loudness: mono
+                .chunks_exact(SPECTROGRAM_WINDOW / 2)
+                .map(|chunk| {
+                    (chunk.iter().map(|sample| sample * sample).sum::<f32>() / chunk.len() as f32)
+                        .sqrt()
+                })
+                .collect(),
+        }
+    }
+
+    /// Uses the cache next to the song when it matches the song
+    pub fn cached(song: &Path, frames: &[Frame], sample_rate: u32) -> Self {
+        let cache = song.with_file_name(WAVEFORM_FILE);
+
+        fs::read(&cache)
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<Self>(&bytes).ok())
+            .filter(|waveform| waveform.frames == frames.len())
+            .filter(|waveform| waveform.sample_rate == sample_rate)
+            .unwrap_or_else(|| {
+                let waveform = Self::analyse(frames, sample_rate);
+                if let Err(error) = bincode::serialize(&waveform)
+                    .map_err(|error| error.to_string())
+                    .and_then(|bytes| fs::write(&cache, bytes).map_err(|error| error.to_string()))
+                {
+                    warn!("Could not cache waveform {}: {error}", cache.display());
+                }
+                waveform
+            })
+    }
+
+    /// Seconds covered by each column of the spectrogram
+    pub fn spectrogram_hop(&self) -> f32 {
+        (SPECTROGRAM_WINDOW / 2) as f32 / self.sample_rate.max(1) as f32
+    }
+
+    fn column_at(&self, time: f32) -> usize {
+        (time.max(0.) / self.spectrogram_hop()) as usize
+    }
+
+    /// RMS loudness at seconds
+    pub fn loudness_at(&self, time: f32) -> f32 {
+        self.loudness
+            .get(self.column_at(time))
+            .copied()
+            .unwrap_or(0.)
+    }
+
+    /// Mean spectrogram level between two frequencies at seconds, in `0..=1`
+    pub fn band_at(&self, time: f32, low: f32, high: f32) -> f32 {
+        let band = |frequency: f32| {
+            let bin = frequency * SPECTROGRAM_WINDOW as f32 / self.sample_rate.max(1) as f32;
+            let band = bin.max(1.).ln() / ((SPECTROGRAM_WINDOW / 2) as f32).ln();
+            ((band * SPECTROGRAM_BANDS as f32) as usize).min(SPECTROGRAM_BANDS - 1)
+        };
+
+        let Some(column) = self.spectrogram.get(self.column_at(time)) else {
+            return 0.;
+        };
+
+        let bands = &column[band(low)..=band(high).max(band(low))];
+        bands.iter().map(|level| *level as f32 / 255.).sum::<f32>() / bands.len() as f32
+    }
+
+    /// Peaks of evenly sized columns over a range of seconds
+    pub fn peaks(&self, from: f32, to: f32, columns: usize) -> Vec<Peak> {
+        let column_frames = (to - from) * self.sample_rate as f32 / columns.max(1) as f32;
+        let depth = (0..self.levels.len())
+            .take_while(|depth| (PEAK_WINDOW << depth) as f32 <= column_frames)
+            .last()
+            .unwrap_or(0);
+
+        let Some(level) = self.levels.get(depth) else {
+            return vec![Peak::default(); columns];
+        };
+
+        let window = (PEAK_WINDOW << depth) as f32;
+        let index = |time: f32| (time * self.sample_rate as f32 / window).max(0.) as usize;
+
+        (0..columns)
+            .map(|column| {
+                let start = from + (to - from) * column as f32 / columns as f32;
+                let end = from + (to - from) * (column + 1) as f32 / columns as f32;
+                let (start, end) = (index(start), index(end).max(index(start) + 1));
+
+                level
+                    .get(start.min(level.len())..end.min(level.len()))
+                    .and_then(|peaks| peaks.iter().copied().reduce(Peak::merge))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// Analyses the song on the async compute pool
+pub fn analyse_song(task: &mut WaveformTask, song: PathBuf, frames: Arc<Vec<Frame>>, rate: u32) {
+    task.0 = AsyncComputeTaskPool::get()
+        .spawn(async move { Waveform::cached(&song, &frames, rate) })
+        .pipe(Some);
+}
+
+pub fn receive_waveform(mut task: ResMut<WaveformTask>, mut waveform: ResMut<Waveform>) {
+    if task.0.as_ref().is_some_and(|task| task.is_finished()) {
+        *waveform = task.0.take().map(future::block_on).unwrap();
+    }
+}
+
+fn spectrogram(mono: &[f32]) -> Vec<Vec<u8>> {
+    let window = (0..SPECTROGRAM_WINDOW)
+        .map(|n| 0.5 - 0.5 * (TAU * n as f32 / SPECTROGRAM_WINDOW as f32).cos())
+        .collect::<Vec<_>>();
+
+    let half = SPECTROGRAM_WINDOW / 2;
+    let bands = (0..=SPECTROGRAM_BANDS)
+        .map(|band| (half as f32).powf(band as f32 / SPECTROGRAM_BANDS as f32) as usize)
+        .collect::<Vec<_>>();
+
+    (0..mono.len() / half)
+        .map(|column| {
+            let mut bins = (0..SPECTROGRAM_WINDOW)
+                .map(|n| mono.get(column * half + n).copied().unwrap_or(0.) * window[n])
+                .map(|sample| (sample, 0.))
+                .collect::<Vec<_>>();
+
+            fft(&mut bins);
+
+            bands
+                .windows(2)
+                .map(|edges| {
+                    let (start, end) = (edges[0], edges[1].max(edges[0] + 1).min(half));
+                    bins[start..end]
+                        .iter()
+                        .map(|(re, im)| (re * re + im * im).sqrt())
+                        .fold(0., f32::max)
+                })
+                .map(|magnitude| 20. * (magnitude * 2. / half as f32).max(1e-9).log10())
+                .map(|db| (1. - db / SPECTROGRAM_FLOOR_DB).clamp(0., 1.))
+                .map(|level| (level * 255.) as u8)
+                .collect()
+        })
+        .collect()
+}
+
+/// In place radix 2 FFT. The length must be a power of 2.
+pub(super) fn fft(bins: &mut [(f32, f32)]) {
+    let len = bins.len();
+    debug_assert!(len.is_power_of_two());
+
+    let bits = len.trailing_zeros();
+    for index in 0..len {
+        let reversed = index.reverse_bits() >> (usize::BITS - bits);
+        if index < reversed {
+            bins.swap(index, reversed);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let angle = -2. * PI / size as f32;
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = bins[start + k + size / 2];
+                let twiddled = (re * cos - im * sin, re * sin + im * cos);
+                let even = bins[start + k];
+                bins[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                bins[start + k + size / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    fn sine(frequency: f32, sample_rate: u32, frames: usize) -> Vec<Frame> {
+        (0..frames)
+            .map(|frame| (frame as f32 / sample_rate as f32 * frequency * TAU).sin())
+            .map(Frame::from_mono)
+            .collect()
+    }
+
+    #[test]
+    fn waveform_levels() {
+        let frames = (0..PEAK_WINDOW * 5)
+            .map(|frame| (frame / PEAK_WINDOW) as f32 * 0.1)
+            .map(Frame::from_mono)
+            .collect::<Vec<_>>();
+
+        let waveform = Waveform::analyse(&frames, 1000);
+
+        assert_eq!(
+            waveform.levels.iter().map(Vec::len).collect::<Vec<_>>(),
+            [5, 3, 2, 1]
+        );
+        assert_eq!(waveform.levels[0][2], Peak { min: 0., max: 0.2 });
+        assert_eq!(waveform.levels[1][1], Peak { min: 0., max: 0.3 });
+        assert_eq!(waveform.levels[3][0], Peak { min: 0., max: 0.4 });
+    }
+
+    #[test]
+    fn waveform_peaks() {
+        let waveform = Waveform::analyse(&sine(10., 25600, 25600 * 4), 25600);
+
+        let peaks = waveform.peaks(0., 4., 8);
+        assert_eq!(peaks.len(), 8);
+        assert!(peaks.iter().all(|peak| 0.99 < peak.max && peak.min < -0.99));
+
+        let outside = waveform.peaks(5., 6., 2);
+        assert_eq!(outside, [Peak::default(); 2]);
+    }
+
+    #[test]
+    fn spectrogram_bands() {
+        let sample_rate = 44100;
+        let waveform = Waveform::analyse(
+            &sine(1000., sample_rate, SPECTROGRAM_WINDOW * 4),
+            sample_rate,
+        );
+
+        let loudest = waveform.spectrogram[2]
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, level)| **level)
+            .map(|(band, _)| band)
+            .unwrap();
+
+        let half = SPECTROGRAM_WINDOW / 2;
+        let bin = 1000. * SPECTROGRAM_WINDOW as f32 / sample_rate as f32;
+        let band = (bin.ln() / (half as f32).ln() * SPECTROGRAM_BANDS as f32) as usize;
+
+        assert_eq!(waveform.spectrogram.len(), 8);
+        assert!(loudest.abs_diff(band) <= 1);
+
+        let time = waveform.spectrogram_hop() * 2.5;
+        assert!(waveform.band_at(time, 800., 1200.) > 2. * waveform.band_at(time, 5000., 8000.));
+        assert!((waveform.loudness_at(time) - FRAC_1_SQRT_2).abs() < 0.01);
+        assert_eq!(waveform.loudness_at(60.), 0.);
+    }
+
+    #[test]
+    fn waveform_cache() {
+        let dir = std::env::temp_dir().join(format!("waveform-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let song = dir.join("song.ogg");
+        let frames = sine(440., 8000, 8000);
+
+        let analysed = Waveform::cached(&song, &frames, 8000);
+        assert!(dir.join(WAVEFORM_FILE).exists());
+
+        let stale = Waveform {
+            frames: 1,
+            ..analysed.clone()
+        };
+        fs::write(dir.join(WAVEFORM_FILE), bincode::serialize(&stale).unwrap()).unwrap();
+        assert_eq!(Waveform::cached(&song, &frames, 8000), analysed);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
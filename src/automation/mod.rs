@@ -2,45 +2,157 @@ use crate::timing::*;
 use crate::utils::*;
 use bevy::prelude::*;
 use noisy_float::prelude::*;
+use serde::{Deserialize, Serialize};
 use tap::Pipe;
 use tinyvec::*;
 
+use std::{
+    f32::consts::{FRAC_PI_2, TAU},
+    num::NonZeroU8,
+};
+
+pub mod reactive;
 pub mod sequence;
 pub mod spline;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    In,
+    Out,
+    InOut,
+}
+
+impl Easing {
+    /// Out and InOut easings are produced by mirroring the In easing
+    #[rustfmt::skip]
+    fn apply(self, x: f32, ease_in: impl Fn(f32) -> f32) -> f32 {
+        match self {
+            Easing::In => ease_in(x),
+            Easing::Out => 1. - ease_in(1. - x),
+            Easing::InOut if x < 0.5 => ease_in(2. * x) / 2.,
+            Easing::InOut => 1. - ease_in(2. - 2. * x) / 2.,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Weight {
     Constant,
     Quadratic(R32),
     Cubic(R32),
+    Sine(Easing),
+    Exponential(Easing),
+    /// Overshoots the unit interval. See [`Lerp::lerp_unclamped`] for how overshoots are handled.
+    Elastic(Easing),
+    Bounce(Easing),
+    /// Overshoots the unit interval. See [`Lerp::lerp_unclamped`] for how overshoots are handled.
+    Back(Easing),
+    /// Equivalent of CSS `steps(n, jump-end)`
+    Steps(NonZeroU8),
+    /// Equivalent of CSS `cubic-bezier(x1, y1, x2, y2)`. Overshoots with control points outside the unit interval
+    CubicBezier {
+        x1: T32,
+        y1: R32,
+        x2: T32,
+        y2: R32,
+    },
 }
 
 impl Weight {
-    #[rustfmt::skip]
     pub fn eval(&self, t: T32) -> T32 {
+        self.eval_unclamped(t).raw().clamp(0., 1.).pipe(t32)
+    }
+
+    /// Output of the easing which overshoots the unit interval for some weights
+    #[rustfmt::skip]
+    pub fn eval_unclamped(&self, t: T32) -> R32 {
         let func = |x: f32, k: f32| (k + k.signum())
             .abs()
             .powf(k.signum())
             .pipe(|power| x.signum() * x.abs().powf(power));
 
         match self {
-            Weight::Constant => t32(1.),
-            Weight::Quadratic(k) => t32(func(t.raw(), k.raw())),
+            Weight::Constant => 1.,
+            Weight::Quadratic(k) => func(t.raw(), k.raw()),
             Weight::Cubic(k) => (2. * t.raw() - 1.)
                 .pipe(|x| func(x, k.raw()))
-                .pipe(|output| (output - 1.) / 2. + 1.)
-                .pipe(t32),
+                .pipe(|output| (output - 1.) / 2. + 1.),
+            Weight::Sine(easing) => easing.apply(t.raw(), |x| 1. - (x * FRAC_PI_2).cos()),
+            Weight::Exponential(easing) => easing
+                .apply(t.raw(), |x| if x <= 0. { 0. } else { 2_f32.powf(10. * x - 10.) }),
+            Weight::Elastic(easing) => {
+                let (period, phase) = match easing {
+                    Easing::InOut => (4.5, 11.125),
+                    _ => (3., 10.75),
+                };
+
+                easing.apply(t.raw(), |x| match x {
+                    x if x <= 0. => 0.,
+                    x if 1. <= x => 1.,
+                    x => -(2_f32.powf(10. * x - 10.)) * ((10. * x - phase) * TAU / period).sin(),
+                })
+            }
+            Weight::Bounce(easing) => easing.apply(t.raw(), |x| 1. - bounce_out(1. - x)),
+            Weight::Back(easing) => {
+                let overshoot = match easing {
+                    Easing::InOut => 1.70158 * 1.525,
+                    _ => 1.70158,
+                };
+
+                easing.apply(t.raw(), |x| x * x * ((overshoot + 1.) * x - overshoot))
+            }
+            Weight::Steps(stairs) => (stairs.get() as f32)
+                .pipe(|stairs| (t.raw() * stairs).floor() / stairs),
+            Weight::CubicBezier { x1, y1, x2, y2 } => match t.raw() {
+                x if x <= 0. || 1. <= x => x,
+                x => solve_bezier_parameter(x, x1.raw(), x2.raw())
+                    .pipe(|s| bezier_component(s, y1.raw(), y2.raw())),
+            },
         }
+        .pipe(r32)
     }
 }
 
+#[rustfmt::skip]
+fn bounce_out(x: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+
+    match x {
+        x if x < 1. / D => N * x * x,
+        x if x < 2. / D => (x - 1.5 / D).pipe(|x| N * x * x + 0.75),
+        x if x < 2.5 / D => (x - 2.25 / D).pipe(|x| N * x * x + 0.9375),
+        x => (x - 2.625 / D).pipe(|x| N * x * x + 0.984375),
+    }
+}
+
+/// 1D cubic bezier with fixed end points at 0 and 1
+fn bezier_component(s: f32, p1: f32, p2: f32) -> f32 {
+    3. * (1. - s).powi(2) * s * p1 + 3. * (1. - s) * s.powi(2) * p2 + s.powi(3)
+}
+
+/// The x component is monotonic when both control points are within the unit interval
+/// so bisection is guaranteed to converge.
+fn solve_bezier_parameter(x: f32, x1: f32, x2: f32) -> f32 {
+    (0..32)
+        .fold((0_f32, 1_f32), |(lo, hi), _| {
+            let mid = (lo + hi) / 2.;
+            if bezier_component(mid, x1, x2) < x {
+                (mid, hi)
+            } else {
+                (lo, mid)
+            }
+        })
+        .pipe(|(lo, hi)| (lo + hi) / 2.)
+}
+
 impl Default for Weight {
     fn default() -> Self {
         Self::Quadratic(r32(0.))
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Anchor<T> {
     pub x: P32,
     pub val: T,
@@ -60,26 +172,102 @@ where
     type Output = <T as Lerp>::Output;
 
     fn lerp(&self, next: &Self, t: T32) -> Self::Output {
-        self.val.lerp(&next.val, next.weight.eval(t))
+        self.val
+            .lerp_unclamped(&next.val, next.weight.eval_unclamped(t))
     }
 }
 
-#[derive(Default, Deref, DerefMut, Component)]
+#[derive(Default, Clone, Deref, DerefMut, Component, Serialize, Deserialize)]
 pub struct Automation<T: Default>(pub Vec<Anchor<T>>);
 
+/// Optional component for automations to smooth out the corners at anchors
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Component, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Each segment is interpolated on its own using the weight of its end anchor
+    #[default]
+    Weighted,
+    /// C1 continuous monotone cubic hermite interpolation through all anchors.
+    /// Weights are ignored and output never leaves the range of the surrounding anchors.
+    MonotoneCubic,
+}
+
 impl Automation<T32> {
+    pub fn play(&self, time: ClampedTime) -> T32 {
+        self.play_with(Interpolation::Weighted, time)
+    }
+
+    #[rustfmt::skip]
+    pub fn play_with(
+        &self,
+        interpolation: Interpolation,
+        ClampedTime { offset, lower_clamp, upper_clamp }: ClampedTime
+    )
+        -> T32
+    {
+        match interpolation {
+            Interpolation::Weighted => self.interp(offset).unwrap_or_else(|anchor| anchor.val),
+            Interpolation::MonotoneCubic => self.monotone_cubic(offset),
+        }
+        .pipe(|t| lower_clamp.lerp(&upper_clamp, t))
+    }
+
+    fn secant(&self, index: usize) -> Option<(f32, f32)> {
+        self.get(index)
+            .zip(self.get(index + 1))
+            .map(|(prev, curr)| ((curr.x - prev.x).raw(), curr.val.raw() - prev.val.raw()))
+            .map(|(span, rise)| (span, if f32::EPSILON < span { rise / span } else { 0. }))
+    }
+
+    // Fritsch-Butland tangents. Bounded by 3x the smallest adjacent slope which
+    // guarantees monotonicity of each segment.
     #[rustfmt::skip]
-    pub fn play(&self, ClampedTime { offset, lower_clamp, upper_clamp }: ClampedTime) -> T32 {
-        self.interp(offset)
-            .unwrap_or_else(|anchor| anchor.val)
-            .pipe(|t| lower_clamp.lerp(&upper_clamp, t))
+    fn tangent(&self, index: usize) -> f32 {
+        match (index.checked_sub(1).and_then(|i| self.secant(i)), self.secant(index)) {
+            (Some((h0, d0)), Some((h1, d1))) if 0. < d0 * d1 => {
+                let (w0, w1) = (2. * h1 + h0, h1 + 2. * h0);
+                3. * (h0 + h1) / (w0 / d0 + w1 / d1)
+            }
+            (Some(_), Some(_)) | (None, None) => 0.,
+            (Some((_, slope)), None) | (None, Some((_, slope))) => slope,
+        }
+    }
+
+    #[rustfmt::skip]
+    fn monotone_cubic(&self, offset: P32) -> T32 {
+        let found = self.at_or_after(offset);
+        let index = self.len() - found.len();
+
+        match found {
+            [prev, curr, ..] if f32::EPSILON < (curr.x - prev.x).raw() => {
+                let span = (curr.x - prev.x).raw();
+                let s = offset.completion_ratio(prev.x, curr.x).raw();
+                let (m0, m1) = (self.tangent(index) * span, self.tangent(index + 1) * span);
+                let (y0, y1) = (prev.val.raw(), curr.val.raw());
+                let (s2, s3) = (s * s, s * s * s);
+
+                ((2. * s3 - 3. * s2 + 1.) * y0
+                    + (s3 - 2. * s2 + s) * m0
+                    + (-2. * s3 + 3. * s2) * y1
+                    + (s3 - s2) * m1)
+                    .clamp(y0.min(y1), y0.max(y1))
+                    .pipe(t32)
+            }
+            [_, curr, ..] => curr.val,
+            [single] => single.val,
+            _ => panic!("Unexpected existing no item control table"),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools;
     use pretty_assertions::{assert_eq, assert_ne};
+    use proptest::prelude::*;
+    use sequence::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use tap::Tap;
     use Weight::*;
 
     #[test]
@@ -124,6 +312,267 @@ mod tests {
         })
     }
 
+    fn easings() -> impl Iterator<Item = Weight> {
+        [Easing::In, Easing::Out, Easing::InOut]
+            .into_iter()
+            .flat_map(|easing| {
+                [
+                    Sine(easing),
+                    Exponential(easing),
+                    Elastic(easing),
+                    Bounce(easing),
+                    Back(easing),
+                ]
+            })
+            .chain((1..=12).flat_map(NonZeroU8::new).map(Steps))
+            .chain(bezier_weights())
+    }
+
+    #[rustfmt::skip]
+    fn bezier_weights() -> impl Iterator<Item = Weight> {
+        [0., 0.25, 0.5, 0.75, 1.].into_iter().tuple_combinations().flat_map(|(x1, x2)| {
+            [(-0.5, 1.5), (0., 1.), (0.1, 1.), (1., 0.), (1.5, -0.5)]
+                .map(|(y1, y2)| CubicBezier { x1: t32(x1), y1: r32(y1), x2: t32(x2), y2: r32(y2) })
+        })
+    }
+
+    fn unit_steps() -> impl Iterator<Item = T32> + Clone {
+        (0..=100).map(|i| t32((i as f32) / 100.))
+    }
+
+    #[test]
+    fn easing_inflections() {
+        easings().for_each(|weight| {
+            assert_eq!(weight.eval(t32(0.)), t32(0.));
+            assert_eq!(weight.eval(t32(1.)), t32(1.));
+        });
+
+        [Sine, Exponential, Elastic, Bounce, Back]
+            .map(|ctor| ctor(Easing::InOut).eval(t32(0.5)))
+            .into_iter()
+            .for_each(|half| assert!((half.raw() - 0.5).abs() < 0.0001, "{half}"));
+    }
+
+    #[rustfmt::skip]
+    fn mirrored_easing() -> impl Strategy<Value = fn(Easing) -> Weight> {
+        prop_oneof![
+            Just(Sine as fn(Easing) -> Weight),
+            Just(Exponential as fn(Easing) -> Weight),
+            Just(Elastic as fn(Easing) -> Weight),
+            Just(Bounce as fn(Easing) -> Weight),
+            Just(Back as fn(Easing) -> Weight),
+        ]
+    }
+
+    fn easing() -> impl Strategy<Value = Easing> {
+        prop_oneof![Just(Easing::In), Just(Easing::Out), Just(Easing::InOut)]
+    }
+
+    #[rustfmt::skip]
+    fn monotonic_weight() -> impl Strategy<Value = Weight> {
+        prop_oneof![
+            easing().prop_map(Sine),
+            easing().prop_map(Exponential),
+            (1..=u8::MAX).prop_map(|stairs| Steps(NonZeroU8::new(stairs).unwrap())),
+            (0_f32..=1., 0_f32..=1., 0_f32..=1., 0_f32..=1.).prop_map(|(x1, y1, x2, y2)| CubicBezier {
+                x1: t32(x1),
+                y1: r32(y1),
+                x2: t32(x2),
+                y2: r32(y2),
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn easing_symmetry(ctor in mirrored_easing(), t in 0_f32..=1.) {
+            let eval = |easing, t: f32| ctor(easing).eval_unclamped(t32(t)).raw();
+
+            let mirrored = 1. - eval(Easing::In, 1. - t);
+            prop_assert!((eval(Easing::Out, t) - mirrored).abs() < 0.0001);
+
+            let reflected = 1. - eval(Easing::InOut, 1. - t);
+            prop_assert!((eval(Easing::InOut, t) - reflected).abs() < 0.0001);
+        }
+
+        #[test]
+        fn easing_growth(weight in monotonic_weight(), t0 in 0_f32..=1., t1 in 0_f32..=1.) {
+            let (t0, t1) = (t0.min(t1), t0.max(t1));
+            prop_assert!(weight.eval_unclamped(t32(t0)) <= weight.eval_unclamped(t32(t1)));
+            prop_assert!((0.0..=1.0).contains(&weight.eval_unclamped(t32(t0)).raw()));
+        }
+
+        #[test]
+        fn easing_clamping(ctor in mirrored_easing(), easing in easing(), t in 0_f32..=1.) {
+            let weight = ctor(easing);
+            let unclamped = weight.eval_unclamped(t32(t)).raw();
+            prop_assert_eq!(weight.eval(t32(t)).raw(), unclamped.clamp(0., 1.));
+        }
+    }
+
+    #[test]
+    fn easing_reference_values() {
+        let close = |weight: Weight, t: f32, expected: f32| {
+            let actual = weight.eval(t32(t)).raw();
+            assert!((actual - expected).abs() < 0.001, "{actual} != {expected}");
+        };
+
+        close(Sine(Easing::In), 0.5, 1. - FRAC_1_SQRT_2);
+        close(Sine(Easing::Out), 0.5, FRAC_1_SQRT_2);
+        close(Exponential(Easing::In), 0.5, 0.03125);
+        close(Exponential(Easing::Out), 0.5, 0.96875);
+        close(Bounce(Easing::Out), 0.5, 0.765625);
+        close(Bounce(Easing::In), 0.5, 0.234375);
+        close(Elastic(Easing::Out), 0.5, 1.);
+        close(Elastic(Easing::In), 0.9, 0.);
+        close(Elastic(Easing::In), 0.95, 0.35355);
+        close(Back(Easing::Out), 0.2, 0.70580);
+        close(Back(Easing::In), 0.2, 0.);
+        close(Back(Easing::In), 0.8, 0.29420);
+
+        let css_ease = || CubicBezier {
+            x1: t32(0.25),
+            y1: r32(0.1),
+            x2: t32(0.25),
+            y2: r32(1.),
+        };
+
+        close(css_ease(), 0.25, 0.40851);
+        close(css_ease(), 0.5, 0.80240);
+        close(css_ease(), 0.75, 0.96004);
+
+        let stairs = || Steps(NonZeroU8::new(4).unwrap());
+        [
+            (0.1, 0.),
+            (0.25, 0.25),
+            (0.3, 0.25),
+            (0.74, 0.5),
+            (0.99, 0.75),
+        ]
+        .into_iter()
+        .for_each(|(t, expected)| close(stairs(), t, expected));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn easing_overshoot() {
+        let close = |weight: Weight, t: f32, expected: f32| {
+            let actual = weight.eval_unclamped(t32(t)).raw();
+            assert!((actual - expected).abs() < 0.001, "{actual} != {expected}");
+        };
+
+        close(Back(Easing::In), 0.2, -0.04645);
+        close(Back(Easing::Out), 0.8, 1.04645);
+        close(Elastic(Easing::In), 0.9, -0.25);
+        close(Elastic(Easing::Out), 0.1, 1.25);
+
+        // Anchors which can represent the overshoot follow the easing past the end values
+        let rotation = Sequence(Automation(vec![
+            Anchor { x: p32(0.), val: Rotation::new(r32(0.)), weight: Constant },
+            Anchor { x: p32(1.), val: Rotation::new(r32(90.)), weight: Back(Easing::Out) },
+        ]));
+
+        assert!((rotation.play(p32(0.8)).raw() - 90. * 1.04645).abs() < 0.01);
+
+        // Unit interval anchors overshoot until they are held at the bounds of the unit interval
+        let dip = back_in(0.2, 0.8).play(ClampedTime::new(p32(0.2))).raw();
+        assert!((dip - (0.2 - 0.6 * 0.04645)).abs() < 0.001, "{dip}");
+        assert_eq!(back_in(0., 1.).play(ClampedTime::new(p32(0.2))), t32(0.));
+        assert_eq!(back_in(0.05, 1.).play(ClampedTime::new(p32(0.4))), t32(0.));
+    }
+
+    #[rustfmt::skip]
+    fn back_in(from: f32, to: f32) -> Automation<T32> {
+        Automation(vec![
+            Anchor { x: p32(0.), val: t32(from), weight: Constant },
+            Anchor { x: p32(1.), val: t32(to), weight: Back(Easing::In) },
+        ])
+    }
+
+    proptest! {
+        #[test]
+        fn overshoot_continuity(from in 0_f32..=1., to in 0_f32..=1., t in 0_f32..0.999) {
+            let play = |t: f32| back_in(from, to).play(ClampedTime::new(p32(t))).raw();
+            let extrapolated = from + (to - from) * Back(Easing::In).eval_unclamped(t32(t)).raw();
+
+            prop_assert!((play(t) - extrapolated.clamp(0., 1.)).abs() < 0.0001);
+            prop_assert!((play(t + 0.001) - play(t)).abs() < 0.01);
+        }
+    }
+
+    #[rustfmt::skip]
+    fn smooth_automation() -> Automation<T32> {
+        Automation(vec![
+            Anchor { x: p32(0.0), val: t32(0.0), weight: Constant },
+            Anchor { x: p32(0.5), val: t32(0.3), weight: Constant },
+            Anchor { x: p32(1.0), val: t32(1.0), weight: Constant },
+            Anchor { x: p32(1.5), val: t32(1.0), weight: Constant },
+            Anchor { x: p32(2.0), val: t32(0.2), weight: Constant },
+            Anchor { x: p32(4.0), val: t32(0.0), weight: Constant },
+            Anchor { x: p32(4.1), val: t32(1.0), weight: Constant },
+        ])
+    }
+
+    fn play_smooth(automation: &Automation<T32>, offset: f32) -> f32 {
+        automation
+            .play_with(Interpolation::MonotoneCubic, ClampedTime::new(p32(offset)))
+            .raw()
+    }
+
+    #[test]
+    fn smooth_automation_anchors() {
+        let automation = smooth_automation();
+
+        automation.iter().for_each(|anchor| {
+            assert_eq!(play_smooth(&automation, anchor.x.raw()), anchor.val.raw());
+        });
+
+        assert_eq!(play_smooth(&automation, 5.), 1.);
+        assert_eq!(play_smooth(&automation, 1.25), 1.);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn smooth_automation_monotonic() {
+        let automation = smooth_automation();
+
+        automation.iter().tuple_windows().for_each(|(prev, curr)| {
+            let (low, high) = (prev.val.min(curr.val).raw(), prev.val.max(curr.val).raw());
+            let rising = prev.val <= curr.val;
+
+            (0..=100)
+                .map(|i| prev.x.raw() + (curr.x - prev.x).raw() * (i as f32) / 100.)
+                .map(|offset| play_smooth(&automation, offset))
+                .tap(|values| values.clone().for_each(|value| {
+                    assert!((low..=high).contains(&value), "{value} not in {low}..={high}")
+                }))
+                .tuple_windows()
+                .for_each(|(v0, v1)| assert!(if rising { v0 <= v1 } else { v1 <= v0 }));
+        })
+    }
+
+    #[test]
+    fn smooth_automation_continuity() {
+        let automation = smooth_automation();
+        let delta = 0.001;
+
+        // Interior anchors that are not local extrema should have matching slopes on both sides
+        [0.5, 1., 2.].into_iter().for_each(|x| {
+            let left = (play_smooth(&automation, x) - play_smooth(&automation, x - delta)) / delta;
+            let right = (play_smooth(&automation, x + delta) - play_smooth(&automation, x)) / delta;
+            assert!((left - right).abs() < 0.01, "{left} != {right}");
+        });
+
+        // Weighted interpolation has a corner at the same anchor
+        let left = (automation.play(ClampedTime::new(p32(2.))).raw()
+            - automation.play(ClampedTime::new(p32(2. - delta))).raw())
+            / delta;
+        let right = (automation.play(ClampedTime::new(p32(2. + delta))).raw()
+            - automation.play(ClampedTime::new(p32(2.))).raw())
+            / delta;
+        assert!(0.01 < (left - right).abs());
+    }
+
     #[test]
     #[rustfmt::skip]
     fn play_automation() {
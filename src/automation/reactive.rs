@@ -0,0 +1,105 @@
+use crate::{audio::*, utils::*};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tap::Pipe;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Signal {
+    /// RMS of the song
+    Loudness,
+    /// Spectrogram level between two frequencies in Hz
+    Band { low: P32, high: P32 },
+}
+
+/// Automation source which follows the song instead of anchors. Used in place of an
+/// [`Automation<T32>`](super::Automation) by the same [`Sources`](crate::harmonizer::arranger::Sources).
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioReactive {
+    pub signal: Signal,
+    /// Multiplier of the signal before it's clamped to the unit interval
+    pub gain: P32,
+    /// Seconds to rise to a louder signal
+    pub attack: P32,
+    /// Seconds to fall to a quieter signal
+    pub release: P32,
+    #[serde(skip)]
+    pub level: T32,
+}
+
+impl AudioReactive {
+    pub fn new(signal: Signal) -> Self {
+        Self {
+            signal,
+            gain: p32(1.),
+            attack: p32(0.01),
+            release: p32(0.2),
+            level: t32(0.),
+        }
+    }
+
+    pub fn target(&self, waveform: &Waveform, time: f32) -> f32 {
+        match self.signal {
+            Signal::Loudness => waveform.loudness_at(time),
+            Signal::Band { low, high } => waveform.band_at(time, low.raw(), high.raw()),
+        }
+        .pipe(|signal| (signal * self.gain.raw()).clamp(0., 1.))
+    }
+
+    /// Moves the level towards the target over a step in seconds
+    pub fn follow(&mut self, target: f32, step: f32) {
+        let level = self.level.raw();
+        let time_constant = match level < target {
+            true => self.attack.raw(),
+            false => self.release.raw(),
+        };
+
+        let coefficient = match f32::EPSILON < time_constant {
+            true => 1. - (-step / time_constant).exp(),
+            false => 1.,
+        };
+
+        self.level = t32((level + (target - level) * coefficient).clamp(0., 1.));
+    }
+}
+
+pub fn react_to_audio(
+    time: Res<Time>,
+    song_info: Res<SongInfo>,
+    waveform: Res<Waveform>,
+    mut sources: Query<&mut AudioReactive>,
+) {
+    let audio_pos = song_info.audio_pos(song_info.pos.raw().into()) as f32;
+
+    sources.iter_mut().for_each(|mut source| {
+        let target = source.target(&waveform, audio_pos);
+        source.follow(target, time.delta_seconds());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_and_release() {
+        let mut source = AudioReactive {
+            attack: p32(0.1),
+            release: p32(0.4),
+            ..AudioReactive::new(Signal::Loudness)
+        };
+
+        source.follow(1., 0.1);
+        assert!((source.level.raw() - (1. - (-1f32).exp())).abs() < 0.0001);
+
+        source.follow(1., 1.);
+        let risen = source.level.raw();
+        assert!(0.99 < risen);
+
+        source.follow(0., 0.1);
+        assert!((source.level.raw() - risen * (-0.25f32).exp()).abs() < 0.0001);
+
+        source.release = p32(0.);
+        source.follow(0., 0.01);
+        assert_eq!(source.level, t32(0.));
+    }
+}
@@ -7,11 +7,13 @@ use tap::Pipe;
 
 use super::{spline::*, *};
 
-#[derive(Deref, DerefMut, Default, Clone, Copy, Component)]
+#[derive(Deref, DerefMut, Default, Clone, Copy, Component, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Scalar<Marker, Type = R32> {
     #[deref]
     #[deref_mut]
     value: Type,
+    #[serde(skip)]
     _phantom: PhantomData<Marker>,
 }
 
@@ -32,6 +34,13 @@ impl<Marker, Type: Lerp<Output = Type>> Lerp for Scalar<Marker, Type> {
             _phantom: PhantomData,
         }
     }
+
+    fn lerp_unclamped(&self, next: &Self, t: R32) -> Self::Output {
+        Self {
+            value: self.value.lerp_unclamped(&next.value, t),
+            _phantom: PhantomData,
+        }
+    }
 }
 
 mod markers {
@@ -50,7 +59,7 @@ pub type Rotation = Scalar<markers::Rotation>;
 pub type Scale = Scalar<markers::Scale>;
 pub type Warp = Scalar<markers::Warp>;
 
-#[derive(Deref, DerefMut, Default, Component, Clone, Copy)]
+#[derive(Deref, DerefMut, Default, Component, Clone, Copy, Serialize, Deserialize)]
 pub struct RGBA(pub [T32; 4]);
 
 impl Lerp for RGBA {
@@ -63,9 +72,17 @@ impl Lerp for RGBA {
             .pipe_ref_mut(|iter| [(); 4].map(|_| iter.next().unwrap()))
             .pipe(RGBA)
     }
+
+    fn lerp_unclamped(&self, other: &Self, t: R32) -> Self::Output {
+        self.iter()
+            .zip(other.iter())
+            .map(|(from, to)| from.lerp_unclamped(to, t))
+            .pipe_ref_mut(|iter| [(); 4].map(|_| iter.next().unwrap()))
+            .pipe(RGBA)
+    }
 }
 
-#[derive(Default, Deref, DerefMut, Component)]
+#[derive(Default, Clone, Deref, DerefMut, Component, Serialize, Deserialize)]
 pub struct Sequence<T: Default>(pub Automation<T>);
 
 impl<T: Default + Clone + Copy + Lerp<Output = T>> Sequence<T> {
@@ -76,15 +93,32 @@ impl<T: Default + Clone + Copy + Lerp<Output = T>> Sequence<T> {
 
 impl Sequence<Spline> {
     #[rustfmt::skip]
-    pub fn play(&self, t: T32, offset: P32) -> Vec2 {
+    fn morph(
+        &self,
+        offset: P32,
+        sample: impl Fn(&Spline) -> Vec2,
+        blend: impl Fn(Vec2, Vec2, f32) -> Vec2,
+    )
+        -> Vec2
+    {
         match self.at_or_after(offset) {
             [prev, curr, ..] => offset
                 .completion_ratio(prev.quantify(), curr.quantify())
-                .pipe(|weight| prev.val.play(t).lerp(curr.val.play(t), weight.raw())),
-            [single] => single.val.play(t),
+                .pipe(|ratio| curr.weight.eval_unclamped(ratio))
+                .pipe(|weight| blend(sample(&prev.val), sample(&curr.val), weight.raw())),
+            [single] => sample(&single.val),
             _ => panic!("Unexpected existing no item control table"),
         }
     }
+
+    pub fn play(&self, t: T32, offset: P32) -> Vec2 {
+        self.morph(offset, |spline| spline.play(t), Vec2::lerp)
+    }
+
+    /// Unit direction of travel. Morphing rotates between the directions of each spline.
+    pub fn tangent(&self, t: T32, offset: P32) -> Vec2 {
+        self.morph(offset, |spline| spline.tangent(t), slerp_tangent)
+    }
 }
 
 // Sequences can either be simple sequences in which case they are enough to produce modulations.
@@ -94,3 +128,76 @@ pub struct PrimarySequence<T>(pub T);
 
 #[derive(Deref, DerefMut, Component, Debug)]
 pub struct SecondarySequence<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tap::Tap;
+    use Weight::*;
+
+    #[rustfmt::skip]
+    fn linear_spline(x: f32, y: f32) -> Spline {
+        Spline {
+            path: vec![Segment { curvature: Curvature::Linear, position: Vec2::new(x, y) }],
+            ..default()
+        }
+        .tap_mut(Spline::resample)
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn play_spline_sequence() {
+        let sequence = Sequence(Automation(vec![
+            Anchor { x: p32(0.0), val: linear_spline(2., 0.), weight: Constant },
+            Anchor { x: p32(1.0), val: linear_spline(0., 2.), weight: Quadratic(r32(0.)) },
+            Anchor { x: p32(2.0), val: linear_spline(2., 0.), weight: Constant },
+            Anchor { x: p32(3.0), val: linear_spline(0., 2.), weight: Quadratic(r32(0.)) },
+        ]));
+
+        let covals = [
+            ((1.0, 0.0), 0.0),
+            ((0.5, 0.5), 0.5),
+            ((0.0, 1.0), 1.0),
+            ((1.0, 0.0), 1.5),
+            ((0.5, 0.5), 2.5),
+            ((0.0, 1.0), 3.5),
+        ];
+
+        covals.iter().for_each(|((x, y), offset)| {
+            let position = sequence.play(t32(0.5), p32(*offset));
+            let expected = Vec2::new(*x, *y);
+            assert!(
+                position.distance(expected) < 0.001,
+                "Offset: {offset}, Expected: {expected}, Position: {position}"
+            )
+        })
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn spline_sequence_tangent() {
+        let sequence = Sequence(Automation(vec![
+            Anchor { x: p32(0.0), val: linear_spline(2., 0.), weight: Constant },
+            Anchor { x: p32(1.0), val: linear_spline(0., 2.), weight: Quadratic(r32(0.)) },
+            Anchor { x: p32(2.0), val: linear_spline(0., -2.), weight: Quadratic(r32(0.)) },
+        ]));
+
+        let covals = [
+            ((1.0, 0.0), 0.0),
+            ((std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2), 0.5),
+            ((0.0, 1.0), 1.0),
+            // Opposite directions rotate through a perpendicular instead of cancelling out
+            ((1.0, 0.0), 1.5),
+            ((0.0, -1.0), 2.0),
+        ];
+
+        covals.iter().for_each(|((x, y), offset)| {
+            let tangent = sequence.tangent(t32(0.5), p32(*offset));
+            let expected = Vec2::new(*x, *y);
+            assert!(
+                tangent.distance(expected) < 0.001,
+                "Offset: {offset}, Expected: {expected}, Tangent: {tangent}"
+            )
+        })
+    }
+}
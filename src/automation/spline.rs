@@ -1,12 +1,13 @@
 use crate::utils::{Orientation, *};
 
-use core::iter::once as iter_once;
+use core::{f32::consts::TAU, iter::once as iter_once};
 
 use bevy::{math::f32::Mat3, prelude::*};
 use itertools::Itertools;
 use lyon::tessellation::geom::*;
 use noisy_float::prelude::*;
-use tap::Pipe;
+use serde::{Deserialize, Serialize};
+use tap::{Pipe, Tap};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SampleKind {
@@ -25,11 +26,13 @@ impl SampleKind {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Sample {
     displacement: P32,
     position: Vec2,
     kind: SampleKind,
+    /// Interior vertex of a flattened curve which the path passes through without a corner
+    smooth: bool,
 }
 
 impl Quantify for Sample {
@@ -57,55 +60,131 @@ impl Lerp for Sample {
     }
 }
 
-#[derive(Clone, Copy)]
+impl Sample {
+    /// Unit direction of travel at `t` between this and the next sample
+    #[rustfmt::skip]
+    fn tangent(&self, next: &Self, t: T32) -> Vec2 {
+        match next.kind {
+            SampleKind::Point => next.position - self.position,
+            SampleKind::CWArc | SampleKind::CCArc => (self.lerp(next, t) - next.position)
+                .perp()
+                * -next.kind.signum(),
+        }
+        .normalize_or_zero()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Curvature {
     Linear,
+    /// Perfect circle arc passing through the control point
     Circular(Vec2),
     Quadratic(Vec2),
     Cubic(Vec2, Vec2),
+    /// Bezier curve of arbitrary degree with the given control points
+    Bezier(Vec<Vec2>),
+    /// Uniform Catmull-Rom chain passing through each of the given points
+    CatmullRom(Vec<Vec2>),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Segment {
-    curvature: Curvature,
-    position: Vec2,
+    pub curvature: Curvature,
+    pub position: Vec2,
 }
 
+/// Sampling tolerance used by splines unless configured otherwise
+const DEFAULT_TOLERANCE: f32 = 0.5;
+/// Upper bound on the number of samples a single curved segment contributes to the LUT
+const MAX_SEGMENT_SAMPLES: usize = 256;
+/// Upper bound on the relative difference between the sampled and actual length of a curve.
+/// Splines are played back at a constant speed along their samples so this bounds the speed error.
+const MAX_SPEED_ERROR: f32 = 0.001;
+
 #[rustfmt::skip]
 impl Segment {
-    fn sample_bezier(
+    /// Flattens a curve adaptively starting from the requested tolerance.
+    /// The tolerance is coarsened until the sample budget is met and then refined
+    /// for as long as the budget allows until the speed error is acceptable.
+    /// Tolerances are floored relative to the hull length of the curve so flattening
+    /// never produces vastly more points than the budget.
+    /// Displacements are scaled to match the actual arc length of the curve.
+    fn sample_curve(
         path_length: &mut P32,
         start: Vec2,
-        points: impl Iterator<Item = Point<f32>>
+        tolerance: P32,
+        hull_length: f32,
+        arc_length: f32,
+        flatten: impl Fn(f32) -> Vec<Vec2>,
     )
         -> Vec<Sample>
     {
-        [start]
-            .into_iter()
-            .chain(points.map(|p| Vec2::new(p.x, p.y)))
+        let polyline_length = |points: &[Vec2]| iter_once(start)
+            .chain(points.iter().copied())
+            .tuple_windows::<(_, _)>()
+            .map(|(prev, curr)| prev.distance(curr))
+            .sum::<f32>();
+
+        let speed_error = |points: &[Vec2]| match arc_length {
+            length if f32::EPSILON < length => 1. - polyline_length(points) / length,
+            _ => 0.,
+        };
+
+        let min_tolerance = (hull_length / MAX_SEGMENT_SAMPLES.pow(2) as f32).max(f32::EPSILON);
+        let mut tolerance = tolerance.raw().max(min_tolerance);
+        let mut points = flatten(tolerance);
+
+        while MAX_SEGMENT_SAMPLES < points.len() {
+            tolerance *= 2.;
+            points = flatten(tolerance);
+        }
+
+        while MAX_SPEED_ERROR < speed_error(&points) && min_tolerance < tolerance {
+            let finer_tolerance = (tolerance / 2.).max(min_tolerance);
+            let finer = flatten(finer_tolerance);
+            if MAX_SEGMENT_SAMPLES < finer.len() {
+                break
+            }
+            tolerance = finer_tolerance;
+            points = finer;
+        }
+
+        let scale = match polyline_length(&points) {
+            length if f32::EPSILON < length && f32::EPSILON < arc_length => arc_length / length,
+            _ => 1.,
+        };
+
+        iter_once(start)
+            .chain(points)
             .tuple_windows::<(_, _)>()
             .map(|(prev, curr)| {
-                *path_length += prev.distance(curr);
+                *path_length += prev.distance(curr) * scale;
                 Sample {
                     position: curr,
                     displacement: *path_length,
-                    kind: SampleKind::Point
+                    kind: SampleKind::Point,
+                    smooth: true,
                 }
             })
             .collect::<Vec<_>>()
+            .tap_mut(|samples| if let Some(last) = samples.last_mut() {
+                last.smooth = false
+            })
     }
 
-    fn sample(&self, path_length: &mut P32, start: Vec2) -> Vec<Sample> {
-        match self.curvature {
+    fn sample(&self, path_length: &mut P32, start: Vec2, tolerance: P32) -> Vec<Sample> {
+        match &self.curvature {
             Curvature::Linear => {
                 *path_length += start.distance(self.position);
                 vec![Sample {
                     position: self.position,
                     displacement: *path_length,
-                    kind: SampleKind::Point
+                    kind: SampleKind::Point,
+                    smooth: false,
                 }]
             },
             Curvature::Circular(ctrl) => {
-                let end = self.position;
+                let (ctrl, end) = (*ctrl, self.position);
                 // https://math.stackexchange.com/a/1460096
                 let m11_determinant = [start, ctrl, end]
                     .map(|point| [point.x, point.y, 1.])
@@ -113,12 +192,15 @@ impl Segment {
                     .transpose()
                     .determinant();
 
-                if m11_determinant.abs() <= f32::EPSILON {
+                let ctrl_dir = [start, ctrl, end].into_iter().orientation();
+
+                if m11_determinant.abs() <= f32::EPSILON || ctrl_dir == Orientation::CoLinear {
                     *path_length += start.distance(self.position);
                     vec![Sample {
                         position: end,
                         displacement: *path_length,
-                        kind: SampleKind::Point
+                        kind: SampleKind::Point,
+                        smooth: false,
                     }]
                 } else {
                     let m12 = [start, ctrl, end]
@@ -136,20 +218,19 @@ impl Segment {
                         -0.5 * (m13.determinant() / m11_determinant),
                     );
 
-                    let (center_to_start, center_to_end, ctrl_dir, arc_dir) = (
-                        center - start,
-                        center - end,
-                        [start, ctrl, end].into_iter().orientation(),
-                        [start, center, end].into_iter().orientation()
-                    );
+                    // Traversing start -> ctrl -> end has the same orientation as the arc.
+                    // So the sweep is the angle from start to end going around in that direction
+                    // which covers both minor and major arcs.
+                    let [start_angle, end_angle] = [start, end]
+                        .map(|point| point - center)
+                        .map(|offset| offset.y.atan2(offset.x));
 
-                    let theta = (center_to_start.length() * center_to_end.length())
-                        .pipe(|denominator| center_to_start.dot(center_to_end) / denominator)
-                        .acos()
-                        .abs()
-                        .pipe(|theta| if ctrl_dir != arc_dir { theta } else { 360. - theta });
+                    let sweep = match ctrl_dir {
+                        Orientation::CounterClockWise => (end_angle - start_angle).rem_euclid(TAU),
+                        _ => (start_angle - end_angle).rem_euclid(TAU),
+                    };
 
-                    *path_length += p32(theta * center.distance(start));
+                    *path_length += p32(sweep * center.distance(start));
 
                     let samples = [
                         (f32::EPSILON <= center.distance(start)).then(|| Sample {
@@ -159,12 +240,14 @@ impl Segment {
                                 Orientation::CounterClockWise => SampleKind::CCArc,
                                 Orientation::ClockWise => SampleKind::CWArc,
                                 _ => unreachable!()
-                            }
+                            },
+                            smooth: false,
                         }),
                         Some(Sample {
                             position: end,
                             kind: SampleKind::Point,
                             displacement: *path_length,
+                            smooth: false,
                         })
                     ];
 
@@ -174,14 +257,17 @@ impl Segment {
             Curvature::Quadratic(ctrl) => {
                 let quadratic = QuadraticBezierSegment {
                     from: start.to_array().into(),
-                    ctrl: ctrl.to_array().into(),
+                    ctrl: (*ctrl).to_array().into(),
                     to: self.position.to_array().into(),
                 };
 
-                Self::sample_bezier(
+                Self::sample_curve(
                     path_length,
                     start,
-                    quadratic.flattened(0.05)
+                    tolerance,
+                    hull_length(&[start, *ctrl, self.position]),
+                    quadratic.length(),
+                    |tolerance| quadratic.flattened(tolerance).map(|p| Vec2::new(p.x, p.y)).collect()
                 )
             }
             Curvature::Cubic(a, b) => {
@@ -192,20 +278,196 @@ impl Segment {
                     to: self.position.to_array().into(),
                 };
 
-                Self::sample_bezier(
+                let hull_length = hull_length(&[start, *a, *b, self.position]);
+
+                Self::sample_curve(
                     path_length,
                     start,
-                    cubic.flattened(0.05)
+                    tolerance,
+                    hull_length,
+                    cubic.approximate_length(hull_length * 1e-5),
+                    |tolerance| cubic.flattened(tolerance).map(|p| Vec2::new(p.x, p.y)).collect()
+                )
+            }
+            Curvature::Bezier(ctrls) => {
+                let points = iter_once(start)
+                    .chain(ctrls.iter().copied())
+                    .chain(iter_once(self.position))
+                    .collect::<Vec<_>>();
+
+                let hull_length = hull_length(&points);
+                let arc_length = flatten_bezier(&points, hull_length * 1e-5)
+                    .pipe(|flattened| [&[start], flattened.as_slice()].concat())
+                    .pipe(|flattened| self::hull_length(&flattened));
+
+                Self::sample_curve(
+                    path_length,
+                    start,
+                    tolerance,
+                    hull_length,
+                    arc_length,
+                    |tolerance| flatten_bezier(&points, tolerance)
+                )
+            }
+            Curvature::CatmullRom(ctrls) => {
+                let cubics = catmull_rom_cubics(
+                    &iter_once(start)
+                        .chain(ctrls.iter().copied())
+                        .chain(iter_once(self.position))
+                        .collect::<Vec<_>>()
+                );
+
+                let hulls = cubics
+                    .iter()
+                    .map(|cubic| [cubic.from, cubic.ctrl1, cubic.ctrl2, cubic.to]
+                        .map(|p| Vec2::new(p.x, p.y))
+                        .pipe_ref(|hull| hull_length(hull))
+                    )
+                    .collect::<Vec<_>>();
+
+                let arc_length = cubics
+                    .iter()
+                    .zip(hulls.iter())
+                    .map(|(cubic, hull_length)| cubic.approximate_length(hull_length * 1e-5))
+                    .sum::<f32>();
+
+                Self::sample_curve(
+                    path_length,
+                    start,
+                    tolerance,
+                    hulls.iter().sum::<f32>(),
+                    arc_length,
+                    |tolerance| cubics
+                        .iter()
+                        .flat_map(|cubic| cubic.flattened(tolerance))
+                        .map(|p| Vec2::new(p.x, p.y))
+                        .collect()
                 )
             }
         }
     }
 }
 
-#[derive(Component, Default)]
+/// Deepest subdivision when flattening beziers of arbitrary degree
+const MAX_BEZIER_DEPTH: u32 = 16;
+
+fn hull_length(points: &[Vec2]) -> f32 {
+    points
+        .iter()
+        .tuple_windows::<(_, _)>()
+        .map(|(prev, curr)| prev.distance(*curr))
+        .sum::<f32>()
+}
+
+fn distance_to_chord(point: Vec2, from: Vec2, to: Vec2) -> f32 {
+    let chord = to - from;
+    match chord.length_squared() {
+        length if f32::EPSILON < length => ((point - from).dot(chord) / length)
+            .clamp(0., 1.)
+            .pipe(|ratio| point.distance(from + chord * ratio)),
+        _ => point.distance(from),
+    }
+}
+
+/// Flattens a bezier curve of arbitrary degree by recursive subdivision until the
+/// control polygon is within `tolerance` of the chord. Excludes the first point.
+fn flatten_bezier(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    fn subdivide(points: &[Vec2], tolerance: f32, depth: u32, flattened: &mut Vec<Vec2>) {
+        let (from, to) = (points[0], points[points.len() - 1]);
+
+        let flat = points[1..points.len() - 1]
+            .iter()
+            .all(|point| distance_to_chord(*point, from, to) <= tolerance);
+
+        if flat || MAX_BEZIER_DEPTH <= depth {
+            flattened.push(to);
+            return;
+        }
+
+        // de Casteljau split at the midpoint
+        let (mut left, mut right, mut level) = (vec![from], vec![to], points.to_vec());
+
+        while 1 < level.len() {
+            level = level
+                .iter()
+                .tuple_windows::<(_, _)>()
+                .map(|(prev, curr)| prev.lerp(*curr, 0.5))
+                .collect();
+            left.push(level[0]);
+            right.push(level[level.len() - 1]);
+        }
+
+        right.reverse();
+        subdivide(&left, tolerance, depth + 1, flattened);
+        subdivide(&right, tolerance, depth + 1, flattened);
+    }
+
+    let mut flattened = vec![];
+    if 1 < points.len() {
+        subdivide(points, tolerance, 0, &mut flattened);
+    }
+    flattened
+}
+
+/// Converts each span of a uniform Catmull-Rom chain into the equivalent cubic bezier.
+/// The chain is extrapolated past its ends the same way osu! does.
+fn catmull_rom_cubics(points: &[Vec2]) -> Vec<CubicBezierSegment<f32>> {
+    (0..points.len().saturating_sub(1))
+        .map(|index| {
+            let v2 = points[index];
+            let v1 = index.checked_sub(1).map_or(v2, |i| points[i]);
+            let v3 = points[index + 1];
+            let v4 = points.get(index + 2).copied().unwrap_or(2. * v3 - v2);
+
+            CubicBezierSegment {
+                from: v2.to_array().into(),
+                ctrl1: (v2 + (v3 - v1) / 6.).to_array().into(),
+                ctrl2: (v3 - (v4 - v2) / 6.).to_array().into(),
+                to: v3.to_array().into(),
+            }
+        })
+        .collect()
+}
+
+/// Only the path is persisted. The LUT is resampled on load.
+#[derive(Clone, Component, Serialize, Deserialize)]
+#[serde(from = "SplinePath")]
 pub struct Spline {
     pub path: Vec<Segment>,
+    #[serde(skip_serializing)]
     pub lut: Vec<Sample>,
+    #[serde(skip_serializing)]
+    pub cursor: Cursor,
+    /// Starting tolerance in world units for sampling curved segments
+    pub tolerance: P32,
+}
+
+impl Default for Spline {
+    fn default() -> Self {
+        Self {
+            path: vec![],
+            lut: vec![],
+            cursor: Cursor::default(),
+            tolerance: p32(DEFAULT_TOLERANCE),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SplinePath {
+    path: Vec<Segment>,
+    tolerance: P32,
+}
+
+impl From<SplinePath> for Spline {
+    fn from(SplinePath { path, tolerance }: SplinePath) -> Self {
+        Spline {
+            path,
+            tolerance,
+            ..default()
+        }
+        .tap_mut(Spline::resample)
+    }
 }
 
 #[rustfmt::skip]
@@ -216,13 +478,14 @@ impl Spline {
         let head = Sample {
             position: Vec2::new(0., 0.),
             displacement: p32(0.),
-            kind: SampleKind::Point
+            kind: SampleKind::Point,
+            smooth: false,
         };
 
         let tail = iter_once(&start)
             .chain(self.path.iter())
             .tuple_windows::<(_, _)>()
-            .scan(p32(0.), |state, (prev, curr)| Some(curr.sample(state, prev.position)))
+            .scan(p32(0.), |state, (prev, curr)| Some(curr.sample(state, prev.position, self.tolerance)))
             .flatten();
 
         self.lut = iter_once(head)
@@ -237,10 +500,76 @@ impl Spline {
             .filter(|length| f32::EPSILON < length.raw())
             .map_or(Vec2::default(), |length| self
                 .lut
-                .interp(length * t.raw())
+                .seek_interp(length * t.raw(), &self.cursor)
                 .unwrap_or_else(|sample| sample.position)
             )
     }
+
+    /// Unit direction of travel at `t`. Zero for paths without length.
+    pub fn tangent(&self, t: T32) -> Vec2 {
+        let Some(length) = self
+            .lut
+            .last()
+            .map(|sample| sample.quantify())
+            .filter(|length| f32::EPSILON < length.raw())
+        else {
+            return Vec2::default()
+        };
+
+        let offset = length * t.raw();
+        let found = self.lut.seek(offset, &self.cursor);
+
+        match found {
+            [prev, curr, ..] => offset
+                .completion_ratio(prev.quantify(), curr.quantify())
+                .pipe(|ratio| match curr.kind {
+                    SampleKind::Point => self.chord_tangent(self.lut.len() - found.len(), ratio),
+                    SampleKind::CWArc | SampleKind::CCArc => prev.tangent(curr, ratio),
+                }),
+            _ => Vec2::default(),
+        }
+    }
+
+    /// Chord directions are blended across the vertices of flattened curves
+    /// so the tangent turns continuously instead of stepping at every sample.
+    #[rustfmt::skip]
+    fn chord_tangent(&self, index: usize, ratio: T32) -> Vec2 {
+        let chord = |index: usize| self.lut[index].tangent(&self.lut[index + 1], ratio);
+        let (prev, curr, current) = (&self.lut[index], &self.lut[index + 1], chord(index));
+
+        let entry = match index.checked_sub(1) {
+            Some(before) if prev.smooth => (chord(before) + current) / 2.,
+            _ => current,
+        };
+
+        let exit = match self.lut.get(index + 2) {
+            Some(_) if curr.smooth => (current + chord(index + 1)) / 2.,
+            _ => current,
+        };
+
+        entry.lerp(exit, ratio.raw()).normalize_or_zero()
+    }
+
+    /// Position along with the heading of the path
+    pub fn play_with_heading(&self, t: T32) -> (Vec2, R32) {
+        (self.play(t), heading(self.tangent(t)))
+    }
+}
+
+/// Heading of a direction in degrees measured counter clockwise from +x
+pub fn heading(tangent: Vec2) -> R32 {
+    r32(tangent.y.atan2(tangent.x).to_degrees())
+}
+
+/// Rotates between directions the shorter way around instead of lerping through zero.
+/// A zero direction takes on the other direction.
+#[rustfmt::skip]
+pub fn slerp_tangent(from: Vec2, to: Vec2, t: f32) -> Vec2 {
+    match (from.normalize_or_zero(), to.normalize_or_zero()) {
+        (from, to) if from == Vec2::ZERO => to,
+        (from, to) if to == Vec2::ZERO => from,
+        (from, to) => Vec2::from_angle(from.angle_between(to) * t).rotate(from),
+    }
 }
 
 struct Sampled;
@@ -254,13 +583,14 @@ impl Property<Spline> for Sampled {
 #[cfg(test)]
 mod tests {
     use super::{super::*, *};
+    use proptest::prelude::*;
+    use tap::Tap;
     use Curvature::*;
 
     #[test]
     #[rustfmt::skip]
     fn play_spline() {
         let mut spline = Spline {
-            lut: vec![],
             path: vec![
                 Segment {
                     curvature: Linear,
@@ -283,6 +613,7 @@ mod tests {
                     position: Vec2::new(0., -1.),
                 },
             ],
+            ..default()
         };
 
         spline.resample();
@@ -325,4 +656,345 @@ mod tests {
             )
         })
     }
+
+    #[rustfmt::skip]
+    fn curved_spline(tolerance: f32) -> Spline {
+        Spline {
+            tolerance: p32(tolerance),
+            path: vec![
+                Segment {
+                    curvature: Cubic(Vec2::new(0., 400.), Vec2::new(200., -400.)),
+                    position: Vec2::new(200., 0.),
+                },
+                Segment {
+                    curvature: Quadratic(Vec2::new(300., 300.)),
+                    position: Vec2::new(400., 0.),
+                },
+            ],
+            ..default()
+        }
+        .tap_mut(Spline::resample)
+    }
+
+    #[test]
+    fn uniform_speed() {
+        let spline = curved_spline(DEFAULT_TOLERANCE);
+        let steps = 500;
+
+        let distances = (0..=steps)
+            .map(|i| spline.play(t32(i as f32 / steps as f32)))
+            .tuple_windows::<(_, _)>()
+            .map(|(prev, curr)| prev.distance(curr))
+            .collect::<Vec<_>>();
+
+        let mean = distances.iter().sum::<f32>() / distances.len() as f32;
+        let expected = spline.lut.last().unwrap().quantify().raw() / steps as f32;
+
+        assert!(
+            (mean - expected).abs() / expected < 0.005,
+            "{mean} != {expected}"
+        );
+
+        // Steps which cut across a corner between samples are slightly shorter
+        distances.iter().for_each(|distance| {
+            assert!(
+                (distance - mean).abs() / mean < 0.025,
+                "{distance} != {mean}"
+            )
+        })
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn zero_tolerance() {
+        let exact = curved_spline(0.);
+        let fine = curved_spline(0.000_001);
+
+        assert!(exact.lut.len() <= 1 + 2 * MAX_SEGMENT_SAMPLES);
+
+        let [exact_length, fine_length] =
+            [&exact, &fine].map(|spline| spline.lut.last().unwrap().quantify().raw());
+
+        assert!((exact_length - fine_length).abs() / fine_length < MAX_SPEED_ERROR);
+
+        // Curves keep their shape instead of collapsing into a chord
+        let quadratic = Spline {
+            tolerance: p32(0.),
+            path: vec![Segment {
+                curvature: Quadratic(Vec2::new(100., 200.)),
+                position: Vec2::new(200., 0.),
+            }],
+            ..default()
+        }
+        .tap_mut(Spline::resample);
+
+        assert!(2 < quadratic.lut.len());
+        assert!(quadratic.play(t32(0.5)).distance(Vec2::new(100., 100.)) < 0.1);
+    }
+
+    #[test]
+    fn bounded_samples() {
+        let coarse = curved_spline(50.);
+        let fine = curved_spline(0.000_001);
+
+        [&coarse, &fine].into_iter().for_each(|spline| {
+            assert!(spline.lut.len() <= 1 + 2 * MAX_SEGMENT_SAMPLES);
+        });
+
+        // The arc length is preserved regardless of the sampling resolution
+        let [coarse_length, fine_length] =
+            [&coarse, &fine].map(|spline| spline.lut.last().unwrap().quantify().raw());
+
+        assert!((coarse_length - fine_length).abs() / fine_length < MAX_SPEED_ERROR);
+        assert!(coarse.lut.len() < fine.lut.len());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn spline_heading() {
+        let spline = Spline {
+            path: vec![
+                Segment { curvature: Linear, position: Vec2::new(1., 0.) },
+                Segment { curvature: Linear, position: Vec2::new(1., 1.) },
+                Segment { curvature: Circular(Vec2::new(2., 2.)), position: Vec2::new(3., 1.) },
+                Segment { curvature: Circular(Vec2::new(2., 2.)), position: Vec2::new(1., 1.) },
+            ],
+            ..default()
+        }
+        .tap_mut(Spline::resample);
+
+        let length = spline.lut.last().unwrap().quantify().raw();
+        let half_turn = std::f32::consts::PI;
+
+        // Displacements along unit radius arcs
+        let covals = [
+            (0.5, 0.),
+            (1.5, 90.),
+            // Clockwise over the top of the circle centered at (2, 1)
+            (2. + half_turn / 2., 0.),
+            (2. + half_turn * 0.75, -45.),
+            // Back counter clockwise over the top
+            (2. + half_turn * 1.5, 180.),
+        ];
+
+        covals.iter().for_each(|(displacement, expected)| {
+            let (position, heading) = spline.play_with_heading(t32(displacement / length));
+            let difference = (heading.raw() - expected).abs();
+            assert!(
+                difference < 0.5 || (difference - 360.).abs() < 0.5,
+                "Displacement: {displacement}, Position: {position}, Heading: {heading}"
+            )
+        });
+
+        assert_eq!(Spline::default().play_with_heading(t32(0.5)), (Vec2::default(), r32(0.)));
+    }
+
+    #[rustfmt::skip]
+    fn single_segment(curvature: Curvature, position: Vec2, tolerance: f32) -> Spline {
+        Spline {
+            path: vec![Segment { curvature, position }],
+            tolerance: p32(tolerance),
+            ..default()
+        }
+        .tap_mut(Spline::resample)
+    }
+
+    fn distance_to_path(spline: &Spline, point: Vec2) -> f32 {
+        spline
+            .lut
+            .iter()
+            .tuple_windows::<(_, _)>()
+            .map(|(prev, curr)| distance_to_chord(point, prev.position, curr.position))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn arbitrary_degree_bezier() {
+        let end = Vec2::new(200., 0.);
+        let [a, b] = [Vec2::new(0., 400.), Vec2::new(200., -400.)];
+
+        let pairs = [
+            (Bezier(vec![]), Linear),
+            (Bezier(vec![a]), Quadratic(a)),
+            (Bezier(vec![a, b]), Cubic(a, b)),
+        ];
+
+        pairs.into_iter().for_each(|(bezier, reference)| {
+            let [bezier, reference] = [bezier, reference]
+                .map(|curvature| single_segment(curvature, end, DEFAULT_TOLERANCE));
+            let [bezier_length, reference_length] = [&bezier, &reference]
+                .map(|spline| spline.lut.last().unwrap().quantify().raw());
+
+            assert!((bezier_length - reference_length).abs() / reference_length < MAX_SPEED_ERROR);
+
+            (0..=20).map(|i| t32(i as f32 / 20.)).for_each(|t| {
+                let [position, expected] = [&bezier, &reference].map(|spline| spline.play(t));
+                assert!(position.distance(expected) < 1., "{position} != {expected}")
+            })
+        });
+
+        // Higher degrees stay within the convex hull and bounded in samples
+        let quintic = single_segment(
+            Bezier(vec![a, b, Vec2::new(300., 300.), Vec2::new(-100., 100.)]),
+            end,
+            DEFAULT_TOLERANCE
+        );
+
+        assert!(quintic.lut.len() <= 1 + MAX_SEGMENT_SAMPLES);
+        assert!(quintic.lut.iter().all(|sample| sample.position.y.abs() <= 400.));
+        assert_eq!(quintic.play(t32(1.)), end);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn catmull_rom_chain() {
+        let points = [Vec2::new(100., 100.), Vec2::new(200., -50.), Vec2::new(300., 80.)];
+        let end = Vec2::new(400., 0.);
+        let spline = single_segment(CatmullRom(points.to_vec()), end, DEFAULT_TOLERANCE);
+
+        // Passes through every point of the chain
+        points.iter().chain([end].iter()).for_each(|point| {
+            assert!(distance_to_path(&spline, *point) < 0.01, "{point}")
+        });
+
+        // Matches the osu! catmull formula midway through the spans
+        let chain = [Vec2::ZERO, points[0], points[1], points[2], end];
+        let catmull = |v1: Vec2, v2: Vec2, v3: Vec2, v4: Vec2, t: f32| 0.5 * (
+            2. * v2
+            + (-v1 + v3) * t
+            + (2. * v1 - 5. * v2 + 4. * v3 - v4) * t * t
+            + (-v1 + 3. * v2 - 3. * v3 + v4) * t * t * t
+        );
+
+        [
+            catmull(chain[0], chain[0], chain[1], chain[2], 0.5),
+            catmull(chain[1], chain[2], chain[3], chain[4], 0.5),
+            catmull(chain[2], chain[3], chain[4], 2. * chain[4] - chain[3], 0.5),
+        ]
+        .into_iter()
+        .for_each(|expected| {
+            let distance = distance_to_path(&spline, expected);
+            assert!(distance <= DEFAULT_TOLERANCE, "{expected}: {distance}")
+        });
+
+        // Without intermediate points the chain is a straight line
+        let line = single_segment(CatmullRom(vec![]), end, DEFAULT_TOLERANCE);
+        assert!((line.lut.last().unwrap().quantify().raw() - 400.).abs() < 0.01);
+        assert!(line.play(t32(0.25)).distance(Vec2::new(100., 0.)) < 0.01);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn zero_tolerance_higher_order_curves() {
+        let end = Vec2::new(400., 0.);
+        let ctrls = vec![Vec2::new(100., 300.), Vec2::new(200., -300.), Vec2::new(300., 300.)];
+
+        [Bezier(ctrls.clone()), CatmullRom(ctrls)].into_iter().for_each(|curvature| {
+            let [exact, coarse] = [0., DEFAULT_TOLERANCE]
+                .map(|tolerance| single_segment(curvature.clone(), end, tolerance));
+
+            let [exact_length, coarse_length] = [&exact, &coarse]
+                .map(|spline| spline.lut.last().unwrap().quantify().raw());
+
+            assert!(exact.lut.len() <= 1 + MAX_SEGMENT_SAMPLES);
+            assert!((exact_length - coarse_length).abs() / exact_length < MAX_SPEED_ERROR);
+            assert!(exact.play(t32(1.)).distance(end) < 0.001);
+        })
+    }
+
+    #[test]
+    fn smooth_heading() {
+        let spline = single_segment(Quadratic(Vec2::new(100., 200.)), Vec2::new(200., 0.), 0.5);
+        let steps = 1000;
+
+        // Chords at this tolerance are several degrees apart
+        assert!(spline.lut.len() < 32);
+
+        (0..=steps)
+            .map(|i| spline.play_with_heading(t32(i as f32 / steps as f32)).1)
+            .tuple_windows::<(_, _)>()
+            .for_each(|(prev, curr)| {
+                let turn = (curr - prev).raw().abs();
+                assert!(turn < 1., "{prev} -> {curr}")
+            });
+
+        let [start, end] = [0., 1.].map(|t| spline.play_with_heading(t32(t)).1.raw());
+        assert!(30. < start && start < 70. && -70. < end && end < -30.);
+    }
+
+    fn circumcenter(a: Vec2, b: Vec2, c: Vec2) -> Vec2 {
+        let d = 2. * (a.x * (b.y - c.y) + b.x * (c.y - a.y) + c.x * (a.y - b.y));
+        let [a2, b2, c2] = [a, b, c].map(|p| p.length_squared());
+        Vec2::new(
+            (a2 * (b.y - c.y) + b2 * (c.y - a.y) + c2 * (a.y - b.y)) / d,
+            (a2 * (c.x - b.x) + b2 * (a.x - c.x) + c2 * (b.x - a.x)) / d,
+        )
+    }
+
+    fn point() -> impl Strategy<Value = Vec2> {
+        (-100_f32..100., -100_f32..100.).prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    proptest! {
+        #[test]
+        fn circular_arcs(start in point(), ctrl in point(), end in point()) {
+            let center = circumcenter(start, ctrl, end);
+            let radius = center.distance(start);
+
+            prop_assume!(radius < 500.);
+            prop_assume!([start.distance(ctrl), ctrl.distance(end), end.distance(start)]
+                .iter()
+                .all(|distance| 1. < *distance));
+
+            let spline = Spline {
+                path: vec![
+                    Segment { curvature: Linear, position: start },
+                    Segment { curvature: Circular(ctrl), position: end },
+                ],
+                ..default()
+            }
+            .tap_mut(Spline::resample);
+
+            let arc_start = start.length();
+            let arc_length = spline.lut.last().unwrap().quantify().raw() - arc_start;
+            let precision = 0.01 + radius * 0.0005;
+
+            let position = |s: f32| spline
+                .lut
+                .interp(p32(arc_start + s))
+                .unwrap_or_else(|sample| sample.position);
+
+            // Endpoints
+            prop_assert!(position(0.).distance(start) < precision);
+            prop_assert!(position(arc_length).distance(end) < precision);
+
+            let steps = 2000;
+            let points = (0..=steps)
+                .map(|i| position(arc_length * i as f32 / steps as f32))
+                .collect::<Vec<_>>();
+
+            // Stays on the circle through all 3 points
+            for point in &points {
+                prop_assert!((point.distance(center) - radius).abs() < precision);
+            }
+
+            // Arc length matches numeric integration of the path
+            let integrated = points
+                .iter()
+                .tuple_windows::<(_, _)>()
+                .map(|(prev, curr)| prev.distance(*curr))
+                .sum::<f32>();
+
+            prop_assert!((integrated - arc_length).abs() < precision * 10.);
+
+            // Moves around the circle in the direction passing through the control point
+            let closest = points
+                .iter()
+                .map(|point| point.distance(ctrl))
+                .fold(f32::MAX, f32::min);
+
+            prop_assert!(closest < precision + arc_length / steps as f32);
+        }
+    }
 }
@@ -0,0 +1,269 @@
+use crate::{audio::*, hit::*, utils::*, GameState, Settings};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+const BPM: f32 = 100.;
+
+/// Beats of the click track
+const BEATS: usize = 36;
+
+/// Beats played before taps are measured
+const LEAD_IN: usize = 4;
+
+/// Seconds of a flash in the visual calibration
+const FLASH: f32 = 0.1;
+
+/// Plays the click track so it isn't held by the paused [`SongChannel`] or the
+/// [`PlaybackRate`] of the editor
+#[derive(Resource, Default)]
+pub struct CalibrationChannel;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CalibrationKind {
+    /// Taps to clicks which are heard
+    #[default]
+    Audio,
+    /// Taps to flashes which are seen while the clicks are muted
+    Visual,
+}
+
+#[derive(Resource, Default)]
+pub struct Calibration {
+    pub kind: CalibrationKind,
+    /// Seconds from each measured beat to its tap
+    pub errors: Vec<f32>,
+    /// Song which is restored once calibration is done
+    song: Option<SongInfo>,
+}
+
+impl Calibration {
+    fn beat_length() -> f32 {
+        60. / BPM
+    }
+
+    /// Seconds from the closest beat to the time. `None` for lead in beats.
+    pub fn beat_error(time: f32) -> Option<f32> {
+        let beat = (time / Self::beat_length()).round();
+        (LEAD_IN as f32 <= beat && beat < BEATS as f32).then(|| time - beat * Self::beat_length())
+    }
+
+    /// Mean and standard deviation of the errors in milliseconds
+    pub fn result(&self) -> Option<(f32, f32)> {
+        let count = self.errors.len() as f32;
+        let mean = self.errors.iter().sum::<f32>() / count;
+        let variance = self
+            .errors
+            .iter()
+            .map(|error| (error - mean).powi(2))
+            .sum::<f32>()
+            / count;
+
+        (2 <= self.errors.len()).then_some((mean * 1000., variance.sqrt() * 1000.))
+    }
+}
+
+fn start_calibration(
+    kind: CalibrationKind,
+    volume: f32,
+    calibration: &mut Calibration,
+    song_info: &mut SongInfo,
+    song_channel: &AudioChannel<SongChannel>,
+    calibration_channel: &AudioChannel<CalibrationChannel>,
+    kira_sources: &mut Assets<KiraSource>,
+) {
+    song_channel.pause();
+
+    calibration.kind = kind;
+    calibration.errors.clear();
+    calibration
+        .song
+        .get_or_insert_with(|| std::mem::take(song_info));
+
+    let volume = match kind {
+        CalibrationKind::Audio => volume,
+        CalibrationKind::Visual => 0.,
+    };
+
+    *song_info = SongInfo {
+        title: "Calibration".into(),
+        dur: p32(BEATS as f32 * Calibration::beat_length()),
+        handle: calibration_channel
+            .play(kira_sources.add(click_track(BPM, BEATS)))
+            .with_volume(volume as f64)
+            .with_playback_rate(1.)
+            .handle(),
+        ..default()
+    };
+}
+
+/// Taps come through the [`HitRegister`] like hits in Play. Either offset is corrected by
+/// the mean error of the taps since hits are registered at the heard position in the chart.
+pub fn collect_taps(hits: Res<HitRegister>, mut calibration: ResMut<Calibration>) {
+    if calibration.song.is_none() {
+        return;
+    }
+
+    calibration.errors.extend(
+        hits.iter()
+            .flatten()
+            .flat_map(|hit| Calibration::beat_error(hit.hit_time.raw())),
+    );
+}
+
+pub fn calibration_screen(
+    mut settings: ResMut<Settings>,
+    mut calibration: ResMut<Calibration>,
+    mut song_info: ResMut<SongInfo>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut instances: ResMut<Assets<KiraInstance>>,
+    mut kira_sources: ResMut<Assets<KiraSource>>,
+    song_channel: Res<AudioChannel<SongChannel>>,
+    calibration_channel: Res<AudioChannel<CalibrationChannel>>,
+    mut contexts: EguiContexts,
+) {
+    egui::Window::new("Calibration")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Tap any hit key on every click after the first {LEAD_IN}. \
+                Visual calibration mutes the clicks so tap on every flash instead."
+            ));
+
+            ui.horizontal(|ui| {
+                [
+                    ("Audio", CalibrationKind::Audio),
+                    ("Visual", CalibrationKind::Visual),
+                ]
+                .into_iter()
+                .filter(|(label, _)| ui.button(*label).clicked())
+                .for_each(|(_, kind)| {
+                    start_calibration(
+                        kind,
+                        settings.volume,
+                        &mut calibration,
+                        &mut song_info,
+                        &song_channel,
+                        &calibration_channel,
+                        &mut kira_sources,
+                    )
+                });
+            });
+
+            if calibration.song.is_some() && calibration.kind == CalibrationKind::Visual {
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(64., 64.), egui::Sense::hover());
+                let since_beat = song_info.visual_pos().raw() % Calibration::beat_length();
+
+                if since_beat < FLASH {
+                    ui.painter().circle_filled(
+                        rect.center(),
+                        rect.width() * 0.5,
+                        ui.visuals().selection.bg_fill,
+                    );
+                }
+            }
+
+            ui.label(format!("Taps: {}", calibration.errors.len()));
+
+            if let Some((mean, spread)) = calibration.result() {
+                ui.label(format!("Offset: {mean:.1}ms ± {spread:.1}ms"));
+
+                let offset = match calibration.kind {
+                    CalibrationKind::Audio => &mut settings.audio_offset,
+                    CalibrationKind::Visual => &mut settings.visual_offset,
+                };
+
+                if ui.button(format!("Apply to {offset:.1}ms")).clicked() {
+                    *offset += mean;
+                    calibration.errors.clear();
+                }
+            }
+
+            if ui.button("Done").clicked() {
+                if let Some(instance) = instances.get_mut(&song_info.handle) {
+                    instance.stop(AudioTween::default());
+                }
+                if let Some(song) = calibration.song.take() {
+                    *song_info = song;
+                }
+                calibration.errors.clear();
+                next_state.set(GameState::Edit);
+            }
+        });
+}
+
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, game: &mut App) {
+        game.init_resource::<Calibration>()
+            .add_audio_channel::<CalibrationChannel>()
+            .add_systems(
+                (collect_taps.after(register_hits), calibration_screen).distributive_run_if(
+                    |state: Res<State<GameState>>| matches!(state.0, GameState::Calibrate),
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_result() {
+        let beat = Calibration::beat_length();
+
+        assert_eq!(Calibration::beat_error(beat), None);
+        assert!((Calibration::beat_error(beat * 5. + 0.02).unwrap() - 0.02).abs() < 0.0001);
+        assert!((Calibration::beat_error(beat * 6. - 0.03).unwrap() + 0.03).abs() < 0.0001);
+        assert_eq!(Calibration::beat_error(beat * BEATS as f32), None);
+
+        let mut calibration = Calibration::default();
+        assert_eq!(calibration.result(), None);
+
+        calibration.errors = vec![0.02, 0.04, 0.03, 0.03];
+        let (mean, spread) = calibration.result().unwrap();
+        assert!((mean - 30.).abs() < 0.01);
+        assert!((spread - 50f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn click_track_plays_while_song_is_paused() {
+        let (song_channel, calibration_channel) = (
+            AudioChannel::<SongChannel>::default(),
+            AudioChannel::<CalibrationChannel>::default(),
+        );
+        let mut calibration = Calibration::default();
+        let mut song_info = SongInfo {
+            title: "Song".into(),
+            ..default()
+        };
+
+        let mut game = App::new();
+        game.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<KiraSource>();
+
+        start_calibration(
+            CalibrationKind::Audio,
+            1.,
+            &mut calibration,
+            &mut song_info,
+            &song_channel,
+            &calibration_channel,
+            &mut game.world.resource_mut::<Assets<KiraSource>>(),
+        );
+
+        assert_eq!(
+            calibration_channel.state(&song_info.handle),
+            PlaybackState::Queued
+        );
+        assert_eq!(
+            song_channel.state(&song_info.handle),
+            PlaybackState::Stopped
+        );
+        assert_eq!(calibration.song.map(|song| song.title), Some("Song".into()));
+    }
+}
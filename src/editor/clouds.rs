@@ -1,3 +1,177 @@
-// TODO:
-// - Transform camera to show entire play region in area not covered by UI
-// - Add bounding box to show play region
+use crate::{
+    play_field::{letterbox, PlayField, PLAY_FIELD},
+    utils::*,
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// Zoom factor for each point scrolled over the play field
+const WHEEL_ZOOM: f32 = 1.002;
+
+/// Zoom and pan of the camera over the play field in Edit
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct CloudView {
+    pub zoom: f32,
+    /// World position at the center of the play field
+    pub pan: Vec2,
+}
+
+impl Default for CloudView {
+    fn default() -> Self {
+        Self {
+            zoom: 1.,
+            pan: Vec2::ZERO,
+        }
+    }
+}
+
+impl CloudView {
+    pub const MIN_ZOOM: f32 = 0.1;
+    pub const MAX_ZOOM: f32 = 20.;
+
+    /// World position at an offset from the center of the play field. Offsets are in world
+    /// units without zoom.
+    pub fn world_at(&self, offset: Vec2) -> Vec2 {
+        self.pan + offset / self.zoom
+    }
+
+    /// Keeps the world position under the offset in place
+    pub fn zoom(&mut self, factor: f32, offset: Vec2) {
+        let anchor = self.world_at(offset);
+        self.zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.pan = anchor - offset / self.zoom;
+    }
+
+    /// Moves the world by an offset so whatever was under the pointer follows it
+    pub fn drag(&mut self, offset: Vec2) {
+        self.pan -= offset / self.zoom;
+    }
+}
+
+/// Letterboxed play field in egui points
+fn play_field_rect(realestate: Realestate<PlayField>) -> egui::Rect {
+    let (pos, size) = (realestate.pos(), realestate.size());
+    let (min, size) = letterbox(Vec2::new(pos.x, pos.y), Vec2::new(size.x, size.y));
+    egui::Rect::from_min_size(egui::pos2(min.x, min.y), egui::vec2(size.x, size.y))
+}
+
+/// Wheel zooms and middle drag pans while the pointer is over the play field. Home or
+/// Ctrl+0 resets the view to fit the play field.
+pub fn navigate_clouds(
+    realestate: Res<Realestate<PlayField>>,
+    mut view: ResMut<CloudView>,
+    mut contexts: EguiContexts,
+) {
+    let ctx = contexts.ctx_mut();
+    let rect = play_field_rect(*realestate);
+    let units = PLAY_FIELD.x / rect.width().max(1.);
+    let offset = |delta: egui::Vec2| Vec2::new(delta.x, -delta.y) * units;
+
+    let (hover, scroll, zoom, middle_down, delta, reset) = ctx.input(|input| {
+        (
+            input.pointer.hover_pos(),
+            input.scroll_delta.y,
+            input.zoom_delta(),
+            input.pointer.middle_down(),
+            input.pointer.delta(),
+            input.key_pressed(egui::Key::Home)
+                || (input.modifiers.command && input.key_pressed(egui::Key::Num0)),
+        )
+    });
+
+    if reset && !ctx.wants_keyboard_input() {
+        view.set_if_neq(CloudView::default());
+    }
+
+    let Some(hover) = hover.filter(|hover| rect.contains(*hover) && !ctx.is_pointer_over_area())
+    else {
+        return;
+    };
+
+    let factor = zoom * WHEEL_ZOOM.powf(scroll);
+    if factor != 1. {
+        view.zoom(factor, offset(hover - rect.center()));
+    }
+
+    if middle_down && delta != egui::Vec2::ZERO {
+        view.drag(offset(delta));
+    }
+}
+
+/// Outline of the play region as it's framed by the view
+pub fn draw_play_region(
+    view: Res<CloudView>,
+    realestate: Res<Realestate<PlayField>>,
+    mut contexts: EguiContexts,
+) {
+    let ctx = contexts.ctx_mut();
+    let rect = play_field_rect(*realestate);
+    let points = rect.width() / PLAY_FIELD.x;
+
+    let corner = |world: Vec2| {
+        let offset = (world - view.pan) * view.zoom * points;
+        rect.center() + egui::vec2(offset.x, -offset.y)
+    };
+
+    let region = egui::Rect::from_two_pos(corner(-PLAY_FIELD / 2.), corner(PLAY_FIELD / 2.));
+
+    ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("play region"),
+    ))
+    .with_clip_rect(rect)
+    .rect_stroke(
+        region,
+        egui::Rounding::none(),
+        ctx.style().visuals.widgets.noninteractive.fg_stroke,
+    );
+}
+
+/// The camera is only moved by the view in Edit
+#[rustfmt::skip]
+pub fn frame_clouds(
+    state: Res<State<GameState>>,
+    view: Res<CloudView>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+) {
+    let view = match state.0 {
+        GameState::Edit => *view,
+        _ => CloudView::default(),
+    };
+
+    cameras.iter_mut().for_each(|(mut transform, mut projection)| {
+        let translation = view.pan.extend(transform.translation.z);
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+        if projection.scale != 1. / view.zoom {
+            projection.scale = 1. / view.zoom;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cloud_view_navigation() {
+        let mut view = CloudView::default();
+        let offset = Vec2::new(400., -200.);
+        let anchor = view.world_at(offset);
+
+        view.zoom(2., offset);
+        assert_eq!(view.zoom, 2.);
+        assert_eq!(view.world_at(offset), anchor);
+        assert_eq!(view.pan, Vec2::new(200., -100.));
+
+        view.drag(Vec2::new(100., 100.));
+        assert_eq!(view.pan, Vec2::new(150., -150.));
+
+        view.zoom(1000., Vec2::ZERO);
+        assert_eq!(view.zoom, CloudView::MAX_ZOOM);
+        assert_eq!(view.pan, Vec2::new(150., -150.));
+    }
+}
@@ -0,0 +1,569 @@
+use super::Selection;
+use crate::{
+    automation::{reactive::AudioReactive, sequence::*, spline::Spline, *},
+    harmonizer::{arranger::*, channels::Channels, repeater::*},
+    hit::*,
+    silhouettes::{Activation, PointCloud, Property as ActivationProperty, Silhouette},
+    timing::TemporalOffsets,
+    utils::*,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use noisy_float::{prelude::*, FloatChecker, NoisyFloat};
+use tap::Pipe;
+
+use std::{mem::discriminant, num::NonZeroU8, ops::RangeInclusive};
+
+pub struct Inspector;
+
+/// Edits which would break the constraints of the noisy float are dropped
+fn edit_float<C: FloatChecker<f32>>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut NoisyFloat<f32, C>,
+    range: RangeInclusive<f32>,
+) -> bool {
+    let mut raw = value.raw();
+
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(
+            egui::DragValue::new(&mut raw)
+                .speed(0.01)
+                .clamp_range(range),
+        )
+    })
+    .inner
+    .changed()
+        && set_valid(value, raw)
+}
+
+fn set_valid<C: FloatChecker<f32>>(value: &mut NoisyFloat<f32, C>, raw: f32) -> bool {
+    NoisyFloat::try_new(raw)
+        .map(|valid| *value = valid)
+        .is_some()
+}
+
+fn edit_p32(ui: &mut egui::Ui, label: &str, value: &mut P32) -> bool {
+    edit_float(ui, label, value, 0.0..=f32::MAX)
+}
+
+fn edit_t32(ui: &mut egui::Ui, label: &str, value: &mut T32) -> bool {
+    edit_float(ui, label, value, 0.0..=1.)
+}
+
+fn edit_r32(ui: &mut egui::Ui, label: &str, value: &mut R32) -> bool {
+    edit_float(ui, label, value, f32::MIN..=f32::MAX)
+}
+
+/// Picks one of the values by the name of its variant
+fn edit_variant<T>(ui: &mut egui::Ui, id: &str, value: &mut T, variants: Vec<(&str, T)>) -> bool {
+    let selected = variants
+        .iter()
+        .find(|(_, variant)| discriminant(variant) == discriminant(value))
+        .map_or("", |(name, _)| *name);
+
+    let mut picked = None;
+
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            variants.into_iter().for_each(|(name, variant)| {
+                if ui.selectable_label(name == selected, name).clicked() && name != selected {
+                    picked = Some(variant);
+                }
+            })
+        });
+
+    picked.map(|variant| *value = variant).is_some()
+}
+
+fn edit_easing(ui: &mut egui::Ui, id: &str, easing: &mut Easing) -> bool {
+    let variants = vec![
+        ("In", Easing::In),
+        ("Out", Easing::Out),
+        ("InOut", Easing::InOut),
+    ];
+    edit_variant(ui, id, easing, variants)
+}
+
+#[rustfmt::skip]
+fn edit_weight(ui: &mut egui::Ui, id: &str, weight: &mut Weight) -> bool {
+    let variants = vec![
+        ("Constant", Weight::Constant),
+        ("Quadratic", Weight::Quadratic(r32(0.))),
+        ("Cubic", Weight::Cubic(r32(0.))),
+        ("Sine", Weight::Sine(Easing::In)),
+        ("Exponential", Weight::Exponential(Easing::In)),
+        ("Elastic", Weight::Elastic(Easing::In)),
+        ("Bounce", Weight::Bounce(Easing::In)),
+        ("Back", Weight::Back(Easing::In)),
+        ("Steps", Weight::Steps(NonZeroU8::MIN)),
+        ("CubicBezier", Weight::CubicBezier { x1: t32(0.25), y1: r32(0.1), x2: t32(0.25), y2: r32(1.) }),
+    ];
+
+    let picked = edit_variant(ui, id, weight, variants);
+
+    let edited = match weight {
+        Weight::Constant => false,
+        Weight::Quadratic(k) | Weight::Cubic(k) => edit_r32(ui, "k", k),
+        Weight::Sine(easing)
+        | Weight::Exponential(easing)
+        | Weight::Elastic(easing)
+        | Weight::Bounce(easing)
+        | Weight::Back(easing) => edit_easing(ui, &format!("{id} easing"), easing),
+        Weight::Steps(steps) => {
+            let mut raw = steps.get();
+            let changed = ui.add(egui::DragValue::new(&mut raw).clamp_range(1..=u8::MAX)).changed();
+            changed && NonZeroU8::new(raw).map(|valid| *steps = valid).is_some()
+        }
+        Weight::CubicBezier { x1, y1, x2, y2 } => [
+            edit_t32(ui, "x1", x1),
+            edit_r32(ui, "y1", y1),
+            edit_t32(ui, "x2", x2),
+            edit_r32(ui, "y2", y2),
+        ]
+        .contains(&true),
+    };
+
+    picked || edited
+}
+
+fn edit_offsets(ui: &mut egui::Ui, offsets: &mut TemporalOffsets) -> bool {
+    [
+        edit_p32(ui, "Start", &mut offsets.start),
+        edit_p32(ui, "Duration", &mut offsets.duration),
+    ]
+    .contains(&true)
+}
+
+/// Name of the channel in its colour with its notes on hover
+fn channel_label(ui: &mut egui::Ui, channels: &Channels, channel: u8) {
+    let meta = channels.get(&channel).cloned().unwrap_or_default();
+    let [r, g, b] = meta.color;
+
+    ui.colored_label(egui::Color32::from_rgb(r, g, b), channels.name(channel))
+        .on_hover_text(format!("#{channel} {}", meta.notes));
+}
+
+/// Ranges are reordered and condensed after every edit. Named channels are listed under the
+/// range they fall in.
+fn edit_coverage(ui: &mut egui::Ui, coverage: &mut ChannelCoverage, channels: &Channels) -> bool {
+    let mut ranges = coverage
+        .0
+        .iter()
+        .map(|range| (range.start(), range.end()))
+        .collect::<Vec<_>>();
+
+    let mut removed = None;
+
+    let edited = ranges
+        .iter_mut()
+        .enumerate()
+        .map(|(index, (start, end))| {
+            let changed = ui
+                .horizontal(|ui| {
+                    let changed = ui.add(egui::DragValue::new(start)).changed()
+                        | ui.add(egui::DragValue::new(end)).changed();
+
+                    if ui
+                        .button("\u{274C}")
+                        .on_hover_text("Remove range")
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+
+                    changed
+                })
+                .inner;
+
+            ui.horizontal_wrapped(|ui| {
+                channels
+                    .iter()
+                    .filter(|(channel, meta)| {
+                        (*start..=*end).contains(channel) && !meta.name.is_empty()
+                    })
+                    .for_each(|(channel, _)| channel_label(ui, channels, *channel));
+            });
+
+            changed
+        })
+        .fold(false, |edited, changed| edited | changed);
+
+    let added = ui.button("Add range").clicked();
+
+    if let Some(index) = removed {
+        ranges.remove(index);
+    }
+    if added {
+        let next = ranges.iter().map(|(_, end)| end.saturating_add(1)).max();
+        ranges.push((next.unwrap_or(0), next.unwrap_or(0)));
+    }
+
+    let changed = edited || added || removed.is_some();
+    if changed {
+        *coverage = valid_coverage(&ranges);
+    }
+    changed
+}
+
+fn valid_coverage(ranges: &[(u8, u8)]) -> ChannelCoverage {
+    ranges
+        .iter()
+        .map(|(start, end)| CoverageRange::new(*start.min(end), *start.max(end)))
+        .collect::<Vec<_>>()
+        .pipe(Ensured::new)
+        .pipe(ChannelCoverage)
+}
+
+/// Layers beyond the hit register can never be hit
+fn edit_response(ui: &mut egui::Ui, response: &mut Response) -> bool {
+    let variants = vec![
+        ("Nil", ResponseKind::Nil),
+        ("Commence", ResponseKind::Commence),
+        ("Switch", ResponseKind::Switch),
+        ("Toggle", ResponseKind::Toggle),
+        ("Follow", ResponseKind::Follow(p32(0.))),
+    ];
+
+    let picked = edit_variant(ui, "response kind", &mut response.kind, variants);
+
+    let excess = match &mut response.kind {
+        ResponseKind::Follow(excess) => edit_p32(ui, "Excess", excess),
+        _ => false,
+    };
+
+    let layer = ui
+        .horizontal(|ui| {
+            ui.label("Layer");
+            ui.add(
+                egui::DragValue::new(&mut response.layer)
+                    .clamp_range(0..=HitRegister::default().len() - 1),
+            )
+        })
+        .inner
+        .changed();
+
+    picked || excess || layer
+}
+
+fn edit_clamp(ui: &mut egui::Ui, id: &str, clamp: &mut RepeaterClamp) -> bool {
+    [
+        edit_t32(ui, "Start", &mut clamp.start),
+        edit_t32(ui, "End", &mut clamp.end),
+        edit_weight(ui, id, &mut clamp.weight),
+    ]
+    .contains(&true)
+}
+
+fn edit_repeater(ui: &mut egui::Ui, repeater: &mut Repeater) -> bool {
+    [
+        edit_p32(ui, "Period", &mut repeater.period),
+        ui.checkbox(&mut repeater.ping_pong, "Ping pong").changed(),
+        {
+            ui.label("Ceil");
+            edit_clamp(ui, "ceil", &mut repeater.ceil)
+        },
+        {
+            ui.label("Floor");
+            edit_clamp(ui, "floor", &mut repeater.floor)
+        },
+    ]
+    .contains(&true)
+}
+
+/// Repetitions step by at least one vertex and take at least a triangle
+fn valid_repeat(step: usize, take: usize) -> ActivationProperty {
+    ActivationProperty::Repeat {
+        step: step.max(1),
+        take: take.max(3),
+    }
+}
+
+#[rustfmt::skip]
+fn edit_activation(ui: &mut egui::Ui, activation: &mut Activation) -> bool {
+    let z = edit_r32(ui, "Z", &mut activation.z);
+
+    let color = ui.horizontal(|ui| {
+        ui.label("Base color");
+        ["r", "g", "b", "a"]
+            .into_iter()
+            .zip(activation.base_color.iter_mut())
+            .map(|(label, channel)| edit_float(ui, label, channel, 0.0..=f32::MAX))
+            .fold(false, |edited, changed| edited | changed)
+    })
+    .inner;
+
+    let silhouette = edit_variant(ui, "silhouette", &mut activation.silhouette, vec![
+        ("Polygon", Silhouette::Polygon),
+        ("Curves", Silhouette::Curves {}),
+    ]);
+
+    let property = edit_variant(ui, "property", &mut activation.property, vec![
+        ("None", ActivationProperty::NA),
+        ("Prompt", ActivationProperty::Prompt { prompts: vec![] }),
+        ("Repeat", valid_repeat(1, 3)),
+    ]);
+
+    let details = match &mut activation.property {
+        ActivationProperty::Prompt { prompts } => {
+            ui.label(format!("{} prompts", prompts.len()));
+            false
+        }
+        ActivationProperty::Repeat { step, take } => {
+            let (mut new_step, mut new_take) = (*step, *take);
+            let changed = ui.horizontal(|ui| {
+                ui.label("Step");
+                let step = ui.add(egui::DragValue::new(&mut new_step).clamp_range(1..=usize::MAX));
+                ui.label("Take");
+                let take = ui.add(egui::DragValue::new(&mut new_take).clamp_range(3..=usize::MAX));
+                step.changed() || take.changed()
+            })
+            .inner;
+
+            if changed {
+                activation.property = valid_repeat(new_step, new_take);
+            }
+            changed
+        }
+        ActivationProperty::NA => false,
+    };
+
+    z || color || silhouette || property || details
+}
+
+/// Picks the main and delegated sources among the entities which can be played
+fn edit_sources<T>(
+    ui: &mut egui::Ui,
+    id: &str,
+    sources: &mut Sources<T>,
+    candidates: &[Entity],
+) -> bool {
+    let pick = |ui: &mut egui::Ui, label: &str, current: Option<Entity>, optional: bool| {
+        let mut picked = current;
+
+        ui.horizontal(|ui| {
+            ui.label(label);
+            egui::ComboBox::from_id_source(format!("{id} {label}"))
+                .selected_text(current.map_or("None".into(), |entity| format!("{entity:?}")))
+                .show_ui(ui, |ui| {
+                    if optional {
+                        ui.selectable_value(&mut picked, None, "None");
+                    }
+                    candidates.iter().for_each(|entity| {
+                        ui.selectable_value(&mut picked, Some(*entity), format!("{entity:?}"));
+                    });
+                });
+        });
+
+        (picked != current).then_some(picked)
+    };
+
+    let main = pick(ui, "Main", Some(*sources.main), false).flatten();
+    let delegation = pick(
+        ui,
+        "Delegation",
+        sources.delegation.map(|delegation| *delegation),
+        true,
+    );
+
+    if let Some(main) = main {
+        sources.main = main.into();
+    }
+    if let Some(delegation) = delegation {
+        sources.delegation = delegation.map(GenID::from);
+    }
+
+    main.is_some() || delegation.is_some()
+}
+
+#[derive(SystemParam)]
+pub struct SequenceSheet<'w, 's, T: Default + Send + Sync + 'static> {
+    sheets: Query<
+        'w,
+        's,
+        (
+            Option<&'static mut PrimarySequence<Sources<Sequence<T>>>>,
+            Option<&'static mut SecondarySequence<Sources<Sequence<T>>>>,
+        ),
+    >,
+    sequences: Query<'w, 's, Entity, With<Sequence<T>>>,
+}
+
+impl<'w, 's, T: Default + Send + Sync + 'static> SequenceSheet<'w, 's, T> {
+    fn edit(&mut self, ui: &mut egui::Ui, title: &str, entity: Entity) {
+        let candidates = self.sequences.iter().collect::<Vec<_>>();
+
+        let Ok((primary, secondary)) = self.sheets.get_mut(entity) else {
+            return;
+        };
+
+        [
+            (
+                "primary",
+                primary.map(|primary| primary.map_unchanged(|primary| &mut primary.0)),
+            ),
+            (
+                "secondary",
+                secondary.map(|secondary| secondary.map_unchanged(|secondary| &mut secondary.0)),
+            ),
+        ]
+        .into_iter()
+        .for_each(|(kind, sources)| {
+            section(ui, &format!("{title} {kind}"), sources, |ui, sources| {
+                edit_sources(ui, &format!("{title} {kind}"), sources, &candidates)
+            })
+        });
+    }
+}
+
+#[derive(SystemParam)]
+pub struct SequenceSheets<'w, 's> {
+    splines: SequenceSheet<'w, 's, Spline>,
+    colors: SequenceSheet<'w, 's, RGBA>,
+    luminosities: SequenceSheet<'w, 's, Luminosity>,
+    scales: SequenceSheet<'w, 's, Scale>,
+    rotations: SequenceSheet<'w, 's, Rotation>,
+}
+
+/// Channels routed to the groups of the cloud of an activation
+fn show_routes(ui: &mut egui::Ui, cloud: &PointCloud, channels: &Channels) {
+    egui::CollapsingHeader::new("Routes")
+        .default_open(true)
+        .show(ui, |ui| {
+            cloud.routes().for_each(|(groups, routed)| {
+                ui.label(groups.join(", "));
+                ui.horizontal_wrapped(|ui| {
+                    routed
+                        .iter()
+                        .for_each(|channel| channel_label(ui, channels, *channel))
+                });
+            })
+        });
+}
+
+/// Components are only marked changed when one of their widgets was edited
+fn section<T>(
+    ui: &mut egui::Ui,
+    title: &str,
+    component: Option<Mut<T>>,
+    widgets: impl FnOnce(&mut egui::Ui, &mut T) -> bool,
+) {
+    let Some(mut component) = component else {
+        return;
+    };
+
+    egui::CollapsingHeader::new(title)
+        .default_open(true)
+        .show(ui, |ui| {
+            if widgets(ui, component.bypass_change_detection()) {
+                component.set_changed();
+            }
+        });
+}
+
+#[rustfmt::skip]
+pub fn inspector(
+    realestate: Res<Realestate<Inspector>>,
+    mut selection: ResMut<Selection>,
+    mut components: Query<(
+        Entity,
+        Option<&mut TemporalOffsets>,
+        Option<&mut ChannelCoverage>,
+        Option<&mut Response>,
+        Option<&mut Repeater>,
+        Option<&mut Activation>,
+        Option<&mut Sources<Automation<T32>>>,
+    )>,
+    automation_sources: Query<Entity, Or<(With<Automation<T32>>, With<AudioReactive>)>>,
+    mut sequence_sheets: SequenceSheets,
+    channels: Res<Channels>,
+    clouds: Query<&PointCloud>,
+    mut contexts: EguiContexts,
+) {
+    egui::Window::new("Inspector")
+        .collapsible(false)
+        .title_bar(false)
+        .fixed_rect(egui::Rect::from(*realestate))
+        .show(contexts.ctx_mut(), |ui| {
+            fixed_layout_bug_workaround(ui);
+
+            let mut selectable = components
+                .iter()
+                .filter(|(_, offsets, ..)| offsets.is_some())
+                .map(|(entity, offsets, ..)| (entity, offsets.map_or(p32(0.), |offsets| offsets.start)))
+                .collect::<Vec<_>>();
+            selectable.sort_by_key(|(_, start)| *start);
+
+            egui::ComboBox::from_id_source("selection")
+                .selected_text(selection.map_or("Nothing selected".into(), |entity| format!("{entity:?}")))
+                .show_ui(ui, |ui| selectable.iter().for_each(|(entity, start)| {
+                    ui.selectable_value(&mut selection.0, Some(*entity), format!("{entity:?} at {start:.3}s"));
+                }));
+
+            let Some((entity, offsets, coverage, response, repeater, activation, sources)) = selection
+                .and_then(|entity| components.get_mut(entity).ok())
+            else {
+                return;
+            };
+
+            let candidates = automation_sources.iter().collect::<Vec<_>>();
+            let cloud = activation
+                .as_ref()
+                .and_then(|activation| clouds.get(activation.parent()).ok());
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                section(ui, "Offsets", offsets, edit_offsets);
+                section(ui, "Coverage", coverage, |ui, coverage| edit_coverage(ui, coverage, &channels));
+                section(ui, "Response", response, edit_response);
+                section(ui, "Repeater", repeater, edit_repeater);
+                section(ui, "Activation", activation, edit_activation);
+                if let Some(cloud) = cloud {
+                    show_routes(ui, cloud, &channels);
+                }
+                section(ui, "Automation", sources, |ui, sources| {
+                    edit_sources(ui, "automation", sources, &candidates)
+                });
+
+                sequence_sheets.splines.edit(ui, "Spline", entity);
+                sequence_sheets.colors.edit(ui, "Color", entity);
+                sequence_sheets.luminosities.edit(ui, "Luminosity", entity);
+                sequence_sheets.scales.edit(ui, "Scale", entity);
+                sequence_sheets.rotations.edit(ui, "Rotation", entity);
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn inspector_validation() {
+        let (mut p, mut t, mut r) = (p32(1.), t32(0.5), r32(2.));
+        assert!(!set_valid(&mut p, -1.));
+        assert!(!set_valid(&mut t, 1.5));
+        assert!(!set_valid(&mut r, f32::NAN));
+        assert_eq!((p, t, r), (p32(1.), t32(0.5), r32(2.)));
+
+        assert!(set_valid(&mut t, 0.25));
+        assert_eq!(t, t32(0.25));
+
+        let coverage = valid_coverage(&[(5, 2), (0, 1), (7, 9)]);
+        assert_eq!(
+            coverage.0.iter().copied().collect::<Vec<_>>(),
+            [CoverageRange::new(0, 5), CoverageRange::new(7, 9)]
+        );
+
+        assert!(matches!(
+            valid_repeat(0, 1),
+            ActivationProperty::Repeat { step: 1, take: 3 }
+        ));
+        assert!(matches!(
+            valid_repeat(2, 4),
+            ActivationProperty::Repeat { step: 2, take: 4 }
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::utils::*;
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+
+/// Smallest fraction of a split either side can be dragged to
+const MIN_RATIO: f32 = 0.05;
+
+/// Points either side of a splitter which can be dragged
+const SPLITTER_REACH: f32 = 3.;
+
+/// Panels of the editor. Each is routed the [`Realestate`] of its marker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Panel {
+    PlayField,
+    Playlist,
+    Inspector,
+    SongControl,
+}
+
+impl Panel {
+    pub const ALL: [Self; 4] = [
+        Self::PlayField,
+        Self::Playlist,
+        Self::Inspector,
+        Self::SongControl,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Side by side
+    Vertical,
+    /// One above the other
+    Horizontal,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    Panel(Panel),
+    Split {
+        direction: Direction,
+        /// Fraction of the area given to the first layout
+        ratio: f32,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutPreset {
+    /// Play field above the timeline with the inspector to its side
+    Compose,
+    /// Most of the height given to the playlist
+    Timeline,
+    /// Play field over most of the window
+    Preview,
+}
+
+impl LayoutPreset {
+    pub const ALL: [Self; 3] = [Self::Compose, Self::Timeline, Self::Preview];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Compose => "Compose",
+            Self::Timeline => "Timeline",
+            Self::Preview => "Preview",
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn layout(self) -> Layout {
+        use Direction::*;
+        use Layout::Panel as P;
+
+        let split = |direction, ratio, first, second| Layout::Split {
+            direction,
+            ratio,
+            first: Box::new(first),
+            second: Box::new(second),
+        };
+
+        let with_song_control = |layout| split(Horizontal, 23. / 24., layout, P(Panel::SongControl));
+
+        match self {
+            Self::Compose => with_song_control(split(
+                Horizontal,
+                15. / 23.,
+                split(Vertical, 0.8, P(Panel::PlayField), P(Panel::Inspector)),
+                P(Panel::Playlist),
+            )),
+            Self::Timeline => with_song_control(split(
+                Vertical,
+                0.8,
+                split(Horizontal, 0.35, P(Panel::PlayField), P(Panel::Playlist)),
+                P(Panel::Inspector),
+            )),
+            Self::Preview => with_song_control(split(
+                Vertical,
+                0.85,
+                split(Horizontal, 0.8, P(Panel::PlayField), P(Panel::Playlist)),
+                P(Panel::Inspector),
+            )),
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        LayoutPreset::Compose.layout()
+    }
+}
+
+#[rustfmt::skip]
+fn split(area: Realestate, direction: Direction, ratio: f32) -> [Realestate; 2] {
+    let ratio = match ratio.is_nan() {
+        true => 0.5,
+        false => ratio.clamp(MIN_RATIO, 1. - MIN_RATIO),
+    };
+
+    let proportions = [ratio, 1. - ratio].map(p32);
+
+    match direction {
+        Direction::Vertical => area.vertical_split(proportions),
+        Direction::Horizontal => area.horizontal_split(proportions),
+    }
+}
+
+impl Layout {
+    /// Areas of every panel in the layout
+    pub fn allocate(&self, area: Realestate) -> Vec<(Panel, Realestate)> {
+        match self {
+            Layout::Panel(panel) => vec![(*panel, area)],
+            Layout::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let [first_area, second_area] = split(area, *direction, *ratio);
+                let mut panels = first.allocate(first_area);
+                panels.extend(second.allocate(second_area));
+                panels
+            }
+        }
+    }
+
+    /// Ratios of every split with the direction and whole area of the split
+    fn splits(&mut self, area: Realestate) -> Vec<(&mut f32, Direction, Realestate)> {
+        match self {
+            Layout::Panel(_) => vec![],
+            Layout::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let [first_area, second_area] = split(area, *direction, *ratio);
+                let mut splits = first.splits(first_area);
+                splits.extend(second.splits(second_area));
+                splits.push((ratio, *direction, area));
+                splits
+            }
+        }
+    }
+
+    /// Splitters are drawn above the panels along the boundary of each split
+    pub fn drag_splitters(&mut self, area: Realestate, ctx: &egui::Context) {
+        self.splits(area)
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, (ratio, direction, area))| {
+                let rect = egui::Rect::from(area);
+                let [first, _] = split(area, direction, *ratio);
+
+                let (handle, cursor) = match direction {
+                    Direction::Vertical => (
+                        egui::Rect::from_x_y_ranges(
+                            first.x1.raw() - SPLITTER_REACH..=first.x1.raw() + SPLITTER_REACH,
+                            rect.y_range(),
+                        ),
+                        egui::CursorIcon::ResizeHorizontal,
+                    ),
+                    Direction::Horizontal => (
+                        egui::Rect::from_x_y_ranges(
+                            rect.x_range(),
+                            first.y1.raw() - SPLITTER_REACH..=first.y1.raw() + SPLITTER_REACH,
+                        ),
+                        egui::CursorIcon::ResizeVertical,
+                    ),
+                };
+
+                egui::Area::new(egui::Id::new("splitter").with(index))
+                    .order(egui::Order::Foreground)
+                    .fixed_pos(handle.min)
+                    .show(ctx, |ui| {
+                        let (_, response) =
+                            ui.allocate_exact_size(handle.size(), egui::Sense::drag());
+
+                        if response.hovered() || response.dragged() {
+                            ctx.set_cursor_icon(cursor);
+                        }
+
+                        if let Some(pointer) = response
+                            .interact_pointer_pos()
+                            .filter(|_| response.dragged())
+                        {
+                            *ratio = match direction {
+                                Direction::Vertical => (pointer.x - rect.min.x) / rect.width(),
+                                Direction::Horizontal => (pointer.y - rect.min.y) / rect.height(),
+                            }
+                            .clamp(MIN_RATIO, 1. - MIN_RATIO);
+                        }
+                    });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn layout_allocation() {
+        let area = Realestate::new((p32(0.), p32(0.)), (p32(1000.), p32(480.)));
+
+        LayoutPreset::ALL.into_iter().for_each(|preset| {
+            let panels = preset.layout().allocate(area);
+            let covered = panels
+                .iter()
+                .map(|(_, area)| (area.width() * area.height()).raw())
+                .sum::<f32>();
+
+            assert_eq!(panels.len(), Panel::ALL.len(), "{preset:?}");
+            assert!(
+                (covered - (area.width() * area.height()).raw()).abs() < 1.,
+                "{preset:?}"
+            );
+        });
+
+        let layout = Layout::Split {
+            direction: Direction::Vertical,
+            ratio: 0.25,
+            first: Box::new(Layout::Panel(Panel::Inspector)),
+            second: Box::new(Layout::Split {
+                direction: Direction::Horizontal,
+                ratio: 0.5,
+                first: Box::new(Layout::Panel(Panel::PlayField)),
+                second: Box::new(Layout::Panel(Panel::Playlist)),
+            }),
+        };
+
+        assert_eq!(
+            layout.allocate(area),
+            vec![
+                (
+                    Panel::Inspector,
+                    Realestate::new((p32(0.), p32(0.)), (p32(250.), p32(480.)))
+                ),
+                (
+                    Panel::PlayField,
+                    Realestate::new((p32(250.), p32(0.)), (p32(1000.), p32(240.)))
+                ),
+                (
+                    Panel::Playlist,
+                    Realestate::new((p32(250.), p32(240.)), (p32(1000.), p32(480.)))
+                ),
+            ]
+        );
+
+        let [_, clamped] = split(area, Direction::Horizontal, 2.);
+        assert!((clamped.height().raw() - 480. * MIN_RATIO).abs() < 0.01);
+    }
+}
@@ -1,12 +1,22 @@
 mod clouds;
+mod inspector;
+mod layout;
 mod playlist;
 
-use crate::{audio::*, utils::*, GameState};
-use bevy::prelude::*;
+use crate::{
+    audio::*, play_field::PlayField, settings::SettingsScreen, timing::TemporalOffsets, utils::*,
+    GameState, Settings,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_egui::{egui, EguiContexts};
+use clouds::*;
+use inspector::*;
+use layout::*;
 use playlist::*;
 use tap::{Pipe, Tap};
 
+pub use layout::Layout;
+
 #[derive(Default, Clone, Copy, Deref, DerefMut, Resource)]
 struct Selection(Option<Entity>);
 
@@ -22,16 +32,25 @@ fn theme(mut contexts: EguiContexts) {
 
 pub struct SongControl;
 
-pub fn song_control(
+fn song_control(
     song_info: Res<SongInfo>,
     realestate: Res<Realestate<SongControl>>,
     song_channel: Res<AudioChannel<SongChannel>>,
+    selection: Res<Selection>,
+    offsets: Query<&TemporalOffsets>,
+    mut loop_region: ResMut<LoopRegion>,
+    mut rate: ResMut<PlaybackRate>,
+    mut metronome: ResMut<Metronome>,
+    mut hitsound_preview: ResMut<HitsoundPreview>,
+    mut settings: ResMut<Settings>,
+    mut settings_screen: ResMut<SettingsScreen>,
+    mut next_state: ResMut<NextState<GameState>>,
     mut instances: ResMut<Assets<KiraInstance>>,
     mut contexts: EguiContexts,
 ) {
     let slider_get_set = |new_pos| {
         if let Some((instance, new_pos)) = instances.get_mut(&song_info.handle).zip(new_pos) {
-            instance.seek_to(new_pos);
+            instance.seek_to(song_info.audio_pos(new_pos));
         }
         song_info.pos.raw().into()
     };
@@ -76,6 +95,63 @@ pub fn song_control(
                     song_channel.pause();
                 }
 
+                let (start, end) = loop_region.0.unwrap_or((p32(0.), song_info.dur));
+
+                if ui.button("A").on_hover_text("Loop from here").clicked() {
+                    loop_region.0 = Some((song_info.pos, end.max(song_info.pos)));
+                }
+
+                if ui.button("B").on_hover_text("Loop until here").clicked() {
+                    loop_region.0 = Some((start.min(song_info.pos), song_info.pos));
+                }
+
+                if ui
+                    .button("\u{1F501}")
+                    .on_hover_text("Loop selection")
+                    .clicked()
+                {
+                    if let Some(offsets) = selection.and_then(|entity| offsets.get(entity).ok()) {
+                        loop_region.0 = Some((offsets.start, offsets.start + offsets.duration));
+                    }
+                }
+
+                if ui
+                    .add_enabled(loop_region.0.is_some(), egui::Button::new("\u{274C}"))
+                    .on_hover_text("Loop the whole song")
+                    .clicked()
+                {
+                    loop_region.0 = None;
+                }
+
+                let mut speed = **rate;
+                egui::DragValue::new(&mut speed)
+                    .clamp_range(PlaybackRate::MIN..=PlaybackRate::MAX)
+                    .speed(0.05)
+                    .fixed_decimals(2)
+                    .suffix("x")
+                    .pipe(|drag_value| ui.add(drag_value));
+                rate.set_if_neq(PlaybackRate::new(speed));
+
+                ui.toggle_value(&mut metronome.enabled, "Metronome");
+                ui.toggle_value(&mut hitsound_preview, "Hitsounds")
+                    .on_hover_text("Play hitsounds at prompt times");
+
+                if ui.button("Calibrate").clicked() {
+                    next_state.set(GameState::Calibrate);
+                }
+
+                ui.toggle_value(&mut settings_screen, "Settings");
+
+                ui.menu_button("Layout", |ui| {
+                    if let Some(preset) = LayoutPreset::ALL
+                        .into_iter()
+                        .find(|preset| ui.button(preset.name()).clicked())
+                    {
+                        settings.editor_layout = preset.layout();
+                        ui.close_menu();
+                    }
+                });
+
                 ui.spacing_mut().slider_width = ui.available_width() - 105.;
 
                 egui::Slider::from_get_set(0.0..=song_info.dur.raw().into(), slider_get_set)
@@ -85,30 +161,73 @@ pub fn song_control(
         });
 }
 
+/// Space is routed to the systems of each panel through the [`Realestate`] of its marker
 #[rustfmt::skip]
 fn reallocate_editor_realestate(
-    window: Query<&Window>,
+    mut settings: ResMut<Settings>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut play_field_realestate: ResMut<Realestate<PlayField>>,
+    mut playlist_realestate: ResMut<Realestate<Playlist>>,
+    mut inspector_realestate: ResMut<Realestate<Inspector>>,
     mut song_control_realestate: ResMut<Realestate<SongControl>>,
+    mut contexts: EguiContexts,
 ) {
     let remaining = window
         .get_single()
         .unwrap()
         .pipe(|Window { resolution: res, .. }| (res.width(), res.height()))
+        .pipe(|(width, height)| (width / settings.ui_scale, height / settings.ui_scale))
         .pipe(|(width, height)| [0., 0., width, height].map(p32))
         .pipe(|[x0, y0, x1, y1]| Realestate::<()>::new((x0, y0), (x1, y1)));
 
-    let [_remaining, song_control] = remaining.horizontal_split([23., 1.].map(p32));
-    *song_control_realestate = song_control.into();
+    let layout = settings
+        .editor_layout
+        .clone()
+        .tap_mut(|layout| layout.drag_splitters(remaining, contexts.ctx_mut()));
+
+    // Panels missing from the layout are given no space rather than keeping their last area
+    let allocated = layout.allocate(remaining);
+    let area = |panel| allocated
+        .iter()
+        .find(|(allocated, _)| *allocated == panel)
+        .map_or_else(Realestate::default, |(_, area)| *area);
+
+    Panel::ALL.into_iter().for_each(|panel| match panel {
+        Panel::PlayField => *play_field_realestate = area(panel).into(),
+        Panel::Playlist => *playlist_realestate = area(panel).into(),
+        Panel::Inspector => *inspector_realestate = area(panel).into(),
+        Panel::SongControl => *song_control_realestate = area(panel).into(),
+    });
+
+    if settings.editor_layout != layout {
+        settings.editor_layout = layout;
+    }
 }
 
 pub struct EditorPlugin;
 
 impl Plugin for EditorPlugin {
     fn build(&self, game: &mut App) {
-        game.init_resource::<Realestate<SongControl>>().add_systems(
-            (theme, reallocate_editor_realestate, song_control).distributive_run_if(
-                |state: Res<State<GameState>>| matches!(state.0, GameState::Edit),
-            ),
-        );
+        game.init_resource::<Realestate<SongControl>>()
+            .init_resource::<Realestate<Playlist>>()
+            .init_resource::<Realestate<Inspector>>()
+            .init_resource::<PlaylistView>()
+            .init_resource::<Selection>()
+            .init_resource::<CloudView>()
+            .add_systems(
+                (
+                    theme,
+                    reallocate_editor_realestate,
+                    song_control,
+                    playlist,
+                    navigate_clouds,
+                    draw_play_region,
+                    inspector,
+                )
+                    .distributive_run_if(|state: Res<State<GameState>>| {
+                        matches!(state.0, GameState::Edit)
+                    }),
+            )
+            .add_system(frame_clouds);
     }
 }
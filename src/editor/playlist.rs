@@ -1,3 +1,319 @@
-use crate::audio::SongInfo;
+use crate::{
+    audio::*,
+    harmonizer::{arranger::ChannelCoverage, channels::*},
+    timing::TempoMap,
+    utils::*,
+};
 use bevy::prelude::*;
-use bevy_egui::{egui::*, *};
+use bevy_egui::{egui, EguiContexts};
+use tap::Pipe;
+
+use std::collections::BTreeSet;
+
+/// Columns of the spectrogram in each texture
+const SPECTROGRAM_CHUNK: usize = 2048;
+
+pub struct Playlist;
+
+/// Range of the song shown in the playlist
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PlaylistView {
+    /// Seconds at the left edge
+    pub start: P32,
+    /// Seconds across the whole width
+    pub span: P32,
+    pub spectrogram: bool,
+    /// Show the detected onsets as markers
+    pub onsets: bool,
+    /// Show the channel list beside the waveform
+    pub channels: bool,
+}
+
+impl Default for PlaylistView {
+    fn default() -> Self {
+        Self {
+            start: p32(0.),
+            span: p32(10.),
+            spectrogram: false,
+            onsets: false,
+            channels: false,
+        }
+    }
+}
+
+impl PlaylistView {
+    /// Seconds at a fraction of the width
+    pub fn time_at(&self, fraction: f32) -> f32 {
+        self.start.raw() + self.span.raw() * fraction
+    }
+
+    /// Fraction of the width at seconds
+    pub fn fraction_at(&self, time: f32) -> f32 {
+        (time - self.start.raw()) / self.span.raw()
+    }
+
+    pub fn scroll(&mut self, fraction: f32) {
+        self.start = p32(self.time_at(fraction).max(0.));
+    }
+
+    /// Keeps the time under the anchor in place
+    pub fn zoom(&mut self, factor: f32, anchor: f32) {
+        let time = self.time_at(anchor);
+        self.span = p32((self.span.raw() / factor).clamp(0.05, 60. * 60.));
+        self.start = p32((time - self.span.raw() * anchor).max(0.));
+    }
+}
+
+/// Spectrogram split into textures which fit the GPU limits
+#[derive(Default)]
+pub struct SpectrogramTextures(Vec<egui::TextureHandle>);
+
+impl SpectrogramTextures {
+    fn build(ctx: &egui::Context, waveform: &Waveform) -> Self {
+        waveform
+            .spectrogram
+            .chunks(SPECTROGRAM_CHUNK)
+            .enumerate()
+            .map(|(index, columns)| {
+                let pixels = (0..SPECTROGRAM_BANDS)
+                    .rev()
+                    .flat_map(|band| columns.iter().map(move |column| column[band]))
+                    .map(|level| {
+                        let squared = (level as u16 * level as u16 / 255) as u8;
+                        egui::Color32::from_rgb(level, squared, level / 2)
+                    })
+                    .collect();
+
+                egui::ColorImage {
+                    size: [columns.len(), SPECTROGRAM_BANDS],
+                    pixels,
+                }
+                .pipe(|image| {
+                    ctx.load_texture(
+                        format!("spectrogram-{index}"),
+                        image,
+                        egui::TextureOptions::LINEAR,
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .pipe(Self)
+    }
+}
+
+/// Channels which are routed to or named. Metadata equal to the default is dropped so it
+/// isn't saved with the chart.
+fn channel_list(
+    ui: &mut egui::Ui,
+    used: &BTreeSet<u8>,
+    channels: &mut ResMut<Channels>,
+    mix: &mut ResMut<Table<ChannelMix>>,
+) {
+    let mut edited = Channels::clone(channels);
+    let listed = used
+        .iter()
+        .copied()
+        .chain(channels.keys().copied())
+        .collect::<BTreeSet<_>>();
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        listed.into_iter().for_each(|channel| {
+            let meta = edited.entry(channel).or_default();
+            let mut toggles = mix[channel as usize];
+
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut meta.color);
+                ui.monospace(format!("{channel:>3}"));
+                ui.add(
+                    egui::TextEdit::singleline(&mut meta.name)
+                        .hint_text("Unnamed")
+                        .desired_width(80.),
+                );
+                ui.menu_button("Notes", |ui| {
+                    ui.text_edit_multiline(&mut meta.notes);
+                })
+                .response
+                .on_hover_text(&meta.notes);
+                ui.toggle_value(&mut toggles.muted, "M")
+                    .on_hover_text("Mute while editing");
+                ui.toggle_value(&mut toggles.soloed, "S")
+                    .on_hover_text("Solo while editing");
+            });
+
+            if mix[channel as usize] != toggles {
+                mix[channel as usize] = toggles;
+            }
+        });
+    });
+
+    edited.retain(|_, meta| *meta != ChannelMeta::default());
+    channels.set_if_neq(edited);
+}
+
+pub fn playlist(
+    song_info: Res<SongInfo>,
+    waveform: Res<Waveform>,
+    detection: Res<TimingDetection>,
+    loop_region: Res<LoopRegion>,
+    realestate: Res<Realestate<Playlist>>,
+    mut view: ResMut<PlaylistView>,
+    mut tempo_map: ResMut<TempoMap>,
+    mut channels: ResMut<Channels>,
+    mut mix: ResMut<Table<ChannelMix>>,
+    coverages: Query<&ChannelCoverage>,
+    mut textures: Local<SpectrogramTextures>,
+    mut contexts: EguiContexts,
+) {
+    if waveform.is_changed() {
+        *textures = SpectrogramTextures::build(contexts.ctx_mut(), &waveform);
+    }
+
+    egui::Window::new("Playlist")
+        .collapsible(false)
+        .title_bar(false)
+        .fixed_rect(egui::Rect::from(*realestate))
+        .show(contexts.ctx_mut(), |ui| {
+            fixed_layout_bug_workaround(ui);
+
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut view.spectrogram, "Spectrogram");
+                ui.toggle_value(&mut view.onsets, "Onsets");
+                ui.toggle_value(&mut view.channels, "Channels");
+
+                let suggestion = detection.tempo_map(4, &song_info);
+                if !suggestion.is_empty() && *tempo_map != suggestion {
+                    let label = format!(
+                        "Use {:.2} BPM from {:.3}s",
+                        detection.bpm, suggestion[0].offset
+                    );
+                    if ui.button(label).clicked() {
+                        *tempo_map = suggestion;
+                    }
+                }
+            });
+
+            if view.channels {
+                let used = coverages
+                    .iter()
+                    .flat_map(|coverage| coverage.iter())
+                    .map(|channel| channel as u8)
+                    .collect();
+
+                egui::SidePanel::left("channels")
+                    .resizable(false)
+                    .show_inside(ui, |ui| channel_list(ui, &used, &mut channels, &mut mix));
+            }
+
+            let (rect, response) =
+                ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+
+            if let Some(hover) = response.hover_pos() {
+                let (scroll, zoom) = ui.input(|input| (input.scroll_delta, input.zoom_delta()));
+                let anchor = (hover.x - rect.left()) / rect.width();
+
+                if zoom != 1. {
+                    view.zoom(zoom, anchor);
+                } else if scroll != egui::Vec2::ZERO {
+                    view.scroll(-(scroll.x + scroll.y) / rect.width());
+                }
+            }
+
+            let view = *view;
+            let x_at = |time: f32| rect.left() + view.fraction_at(time) * rect.width();
+
+            // The view is in chart time while the analysis of the song is in audio time
+            if view.spectrogram {
+                let hop = waveform.spectrogram_hop();
+
+                for (index, texture) in textures.0.iter().enumerate() {
+                    let columns = texture.size()[0] as f32;
+                    let start = (index * SPECTROGRAM_CHUNK) as f32 * hop - song_info.offset.raw();
+                    let end = start + columns * hop;
+
+                    painter.image(
+                        texture.id(),
+                        egui::Rect::from_x_y_ranges(x_at(start)..=x_at(end), rect.y_range()),
+                        egui::Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.)),
+                        egui::Color32::WHITE,
+                    );
+                }
+            }
+
+            let stroke = ui.visuals().widgets.inactive.fg_stroke;
+            let (middle, amplitude) = (rect.center().y, rect.height() * 0.5);
+            let end = view.time_at(1.);
+
+            waveform
+                .peaks(
+                    song_info.audio_pos(view.start.raw().into()) as f32,
+                    song_info.audio_pos(end.into()) as f32,
+                    rect.width() as usize,
+                )
+                .into_iter()
+                .enumerate()
+                .map(|(column, peak)| (rect.left() + column as f32, peak))
+                .for_each(|(x, peak)| {
+                    painter.line_segment(
+                        [
+                            egui::pos2(x, middle - peak.max * amplitude),
+                            egui::pos2(x, middle - peak.min * amplitude),
+                        ],
+                        stroke,
+                    )
+                });
+
+            if view.onsets {
+                let stroke = egui::Stroke::new(1., ui.visuals().warn_fg_color);
+                detection
+                    .chart_onsets(&song_info)
+                    .map(|onset| x_at(onset.raw()))
+                    .filter(|x| rect.x_range().contains(x))
+                    .for_each(|x| painter.vline(x, rect.y_range(), stroke));
+            }
+
+            if let Some((start, end)) = loop_region.0 {
+                painter.rect_filled(
+                    egui::Rect::from_x_y_ranges(
+                        x_at(start.raw())..=x_at(end.raw()),
+                        rect.y_range(),
+                    ),
+                    egui::Rounding::none(),
+                    ui.visuals().selection.bg_fill.linear_multiply(0.2),
+                );
+            }
+
+            painter.vline(
+                x_at(song_info.pos.raw()),
+                rect.y_range(),
+                ui.visuals().selection.stroke,
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn playlist_view() {
+        let mut view = PlaylistView {
+            start: p32(10.),
+            span: p32(10.),
+            spectrogram: false,
+            onsets: false,
+            channels: false,
+        };
+
+        view.zoom(2., 0.5);
+        assert_eq!((view.start, view.span), (p32(12.5), p32(5.)));
+        assert_eq!(view.time_at(0.5), 15.);
+
+        view.scroll(-0.5);
+        assert_eq!(view.start, p32(10.));
+
+        view.scroll(-4.);
+        assert_eq!(view.start, p32(0.));
+    }
+}
@@ -1,9 +1,14 @@
 use super::*;
-use crate::{timing::*, utils::*, *};
+use crate::{
+    timing::*,
+    utils::{Property, *},
+    *,
+};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use tap::{Pipe, Tap};
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct CoverageRange(u8, u8);
 
 impl CoverageRange {
@@ -12,6 +17,14 @@ impl CoverageRange {
         CoverageRange(start, end)
     }
 
+    pub fn start(&self) -> u8 {
+        self.0
+    }
+
+    pub fn end(&self) -> u8 {
+        self.1
+    }
+
     pub fn contains(&self, value: u8) -> bool {
         (self.0..=self.1).contains(&value)
     }
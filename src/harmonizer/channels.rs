@@ -0,0 +1,86 @@
+use crate::utils::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// What a channel is for so mappers don't have to remember what each index does
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMeta {
+    pub name: String,
+    /// sRGB
+    pub color: [u8; 3],
+    pub notes: String,
+}
+
+impl Default for ChannelMeta {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: [160, 160, 160],
+            notes: String::new(),
+        }
+    }
+}
+
+/// Metadata of the channels of a chart by index. Channels without metadata aren't stored.
+#[derive(
+    Resource, Clone, Debug, Default, PartialEq, Eq, Deref, DerefMut, Serialize, Deserialize,
+)]
+pub struct Channels(pub BTreeMap<u8, ChannelMeta>);
+
+impl Channels {
+    /// Name of the channel or its index when it has none
+    pub fn name(&self, channel: u8) -> String {
+        self.get(&channel)
+            .filter(|meta| !meta.name.is_empty())
+            .map_or_else(|| format!("#{channel}"), |meta| meta.name.clone())
+    }
+}
+
+/// Editor toggles which aren't saved with the chart
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelMix {
+    pub muted: bool,
+    pub soloed: bool,
+}
+
+impl Table<ChannelMix> {
+    /// Muted channels are silent and once any channel is soloed only soloed channels play
+    pub fn audible(&self, channel: usize) -> bool {
+        let mix = self[channel];
+        !mix.muted && (mix.soloed || !self.iter().any(|mix| mix.soloed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mute_and_solo() {
+        let mut mix = Table::<ChannelMix>::default();
+        assert!((0..MAX_CHANNELS).all(|channel| mix.audible(channel)));
+
+        mix[3].muted = true;
+        assert!(!mix.audible(3));
+        assert!(mix.audible(4));
+
+        mix[5].soloed = true;
+        mix[6].soloed = true;
+        mix[6].muted = true;
+        assert!(mix.audible(5));
+        assert!(!mix.audible(4));
+        assert!(!mix.audible(6));
+
+        let channels = Channels(BTreeMap::from([(
+            37,
+            ChannelMeta {
+                name: "Bass".into(),
+                ..default()
+            },
+        )]));
+        assert_eq!(channels.name(37), "Bass");
+        assert_eq!(channels.name(38), "#38");
+    }
+}
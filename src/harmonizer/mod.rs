@@ -1,16 +1,19 @@
 pub mod arranger;
+pub mod channels;
 pub mod repeater;
 
 use arranger::*;
+use channels::*;
 use repeater::*;
 
 use crate::{
     audio::update_playback,
-    automation::{sequence::*, spline::*, *},
+    automation::{reactive::*, sequence::*, spline::*, *},
     hit::*,
     map_selected,
     timing::*,
     utils::*,
+    GameState,
 };
 
 use core::iter::once as iter_once;
@@ -26,13 +29,11 @@ pub enum Modulation {
     Luminosity(T32),
     Rotation(R32),
     Scale(R32),
-    Translation(Vec2),
-}
-
-impl From<Vec2> for Modulation {
-    fn from(point: Vec2) -> Self {
-        Self::Translation(point)
-    }
+    Translation {
+        shift: Vec2,
+        /// Direction of the path in degrees like other rotations
+        heading: R32,
+    },
 }
 
 impl From<RGBA> for Modulation {
@@ -83,11 +84,19 @@ impl<'w, 's, T: Component + Default> Ensemble<'w, 's, T> {
 impl<'w, 's> Ensemble<'w, 's, Spline> {
     #[rustfmt::skip]
     fn play(&self, channel: usize,  t: T32) -> Option<Modulation> {
-        self.get(channel).and_then(|arrangement| arrangement
-            .secondary
-            .is_none()
-            .then(|| arrangement.primary.play(t, arrangement.offset).into())
-        )
+        self.get(channel).map(|arrangement| {
+            let (offset, primary) = (arrangement.offset, arrangement.primary);
+
+            let (shift, tangent) = match arrangement.secondary {
+                Some(secondary) => (
+                    primary.play(t, offset).lerp(secondary.play(t, offset), t.raw()),
+                    slerp_tangent(primary.tangent(t, offset), secondary.tangent(t, offset), t.raw()),
+                ),
+                None => (primary.play(t, offset), primary.tangent(t, offset)),
+            };
+
+            Modulation::Translation { shift, heading: heading(tangent) }
+        })
     }
 }
 
@@ -127,14 +136,17 @@ pub struct Performers<'w, 's> {
     rotations: Ensemble<'w, 's, Rotation>,
 }
 
+/// Muted channels and channels which aren't soloed are skipped while editing
 #[rustfmt::skip]
 pub fn harmonize(
+    state: Res<State<GameState>>,
+    mix: Res<Table<ChannelMix>>,
     mut modulations: ResMut<Table<Option<Modulation>>>,
     seek_times: Res<Table<SeekTime>>,
     clamped_times: Res<Table<ClampedTime>>,
     delegations: Res<Table<Delegated>>,
     performers: Performers,
-    automation_sources: Query<&Automation<T32>>,
+    automation_sources: Query<(AnyOf<(&Automation<T32>, &AudioReactive)>, Option<&Interpolation>)>,
     automations: Query<(
         &TemporalOffsets,
         &ChannelCoverage,
@@ -144,6 +156,8 @@ pub fn harmonize(
 
     modulations.fill_with(|| None);
 
+    let audible = |index: usize| !matches!(state.0, GameState::Edit) || mix.audible(index);
+
     // First produce modulations with overlapping arrangements consisting of a
     //  - Primary sequence
     //  - Secondary sequence
@@ -152,14 +166,19 @@ pub fn harmonize(
     // Automations have to be arranged seperately because their offset has to be shifted
     // And because they do not have primary and secondary smenatics like sequences
     automations.iter().for_each(|(offsets, coverage, automation)| {
-        coverage.iter().for_each(|index| {
+        coverage.iter().filter(|index| audible(*index)).for_each(|index| {
             if let Some(t) = [clamped_times[index], ClampedTime::new(*seek_times[index])]
                 .iter_mut()
                 .find(|clamped_time| offsets.playable_at(clamped_time.offset))
                 .tap_some_mut(|clamped_time| clamped_time.offset -= offsets.start)
                 .and_then(|time| automation_sources
                     .get(*automation.pick(*delegations[index]))
-                    .map(|automation| automation.play(*time))
+                    .map(|((automation, reactive), interpolation)| match (automation, reactive) {
+                        (_, Some(reactive)) => reactive.level,
+                        (Some(automation), None) => automation
+                            .play_with(interpolation.copied().unwrap_or_default(), *time),
+                        (None, None) => unreachable!(),
+                    })
                     .ok()
                 )
             {
@@ -183,7 +202,7 @@ pub fn harmonize(
     modulations
         .iter_mut()
         .enumerate()
-        .filter(|(_, modulation)|  modulation.is_none())
+        .filter(|(index, modulation)| modulation.is_none() && audible(*index))
         .for_each(|(index, modulation)| {
             let performances = [
                 performers.colors.play_primary(index),
@@ -216,7 +235,11 @@ impl Plugin for HarmonizerPlugin {
         game.init_resource::<Table<SeekTime>>()
             .init_resource::<Table<ClampedTime>>()
             .init_resource::<Table<Delegated>>()
+            .init_resource::<Table<ChannelMix>>()
+            .init_resource::<Channels>()
             .init_resource::<HitRegister>()
+            .init_resource::<HitBindings>()
+            .add_event::<PromptJudged>()
             .init_resource::<SequenceArrangements<Spline>>()
             .init_resource::<SequenceArrangements<RGBA>>()
             .init_resource::<SequenceArrangements<Luminosity>>()
@@ -227,7 +250,18 @@ impl Plugin for HarmonizerPlugin {
                 .chain()
                 .after(update_playback)
             )
-            .add_systems((respond_to_hits, produce_repetitions)
+            .add_system(register_hits
+                .after(update_playback)
+                .before(PreArrange)
+            )
+            .add_systems((
+                    rewind_responses,
+                    rewind_judgements,
+                    respond_to_hits,
+                    judge_prompts,
+                    produce_repetitions,
+                    react_to_audio,
+                )
                 .chain()
                 .in_set(PreArrange)
                 .distributive_run_if(map_selected)
@@ -248,3 +282,69 @@ impl Plugin for HarmonizerPlugin {
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+    use tap::{Pipe, Tap};
+
+    #[rustfmt::skip]
+    fn linear_sequence(x: f32, y: f32) -> Sequence<Spline> {
+        Spline {
+            path: vec![Segment { curvature: Curvature::Linear, position: Vec2::new(x, y) }],
+            ..default()
+        }
+        .tap_mut(Spline::resample)
+        .pipe(|spline| Sequence(Automation(vec![Anchor { val: spline, ..default() }])))
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn play_spline_ensemble() {
+        let mut world = World::new();
+        let primary = world.spawn(linear_sequence(2., 0.)).id();
+        let secondary = world.spawn(linear_sequence(0., 2.)).id();
+
+        world.insert_resource(SequenceArrangements::<Spline>::default().tap_mut(|arrangements| {
+            arrangements[0] = Some(Arrangement {
+                offset: p32(0.),
+                primary: primary.into(),
+                secondary: Some(secondary.into()),
+            });
+            arrangements[1] = Some(Arrangement {
+                offset: p32(0.),
+                primary: primary.into(),
+                secondary: None,
+            });
+        }));
+
+        let mut state = SystemState::<Ensemble<Spline>>::new(&mut world);
+        let ensemble = state.get(&world);
+
+        // Both splines are played at t and morphed from the primary to the secondary by t
+        let covals = [
+            (0., (0., 0.), 0.),
+            (0.25, (0.375, 0.125), 22.5),
+            (0.5, (0.5, 0.5), 45.),
+            (0.75, (0.375, 1.125), 67.5),
+            (1., (0., 2.), 90.),
+        ];
+
+        covals.into_iter().for_each(|(t, (x, y), expected_heading)| {
+            let Some(Modulation::Translation { shift, heading }) = ensemble.play(0, t32(t)) else {
+                panic!("Expected a translation")
+            };
+            assert!(shift.distance(Vec2::new(x, y)) < 0.001, "{t}: {shift}");
+            assert!((heading.raw() - expected_heading).abs() < 0.01, "{t}: {heading}");
+
+            let Some(Modulation::Translation { shift, heading }) = ensemble.play(1, t32(t)) else {
+                panic!("Expected a translation")
+            };
+            assert!(shift.distance(Vec2::new(2. * t, 0.)) < 0.001, "{t}: {shift}");
+            assert!(heading.raw().abs() < 0.01, "{t}: {heading}");
+        });
+
+        assert!(ensemble.play(2, t32(0.5)).is_none());
+    }
+}
@@ -1,11 +1,13 @@
 use super::*;
 use crate::{audio::SongInfo, automation::*, utils::*};
 use noisy_float::prelude::*;
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RepeaterClamp {
-    start: T32,
-    end: T32,
-    weight: Weight,
+    pub start: T32,
+    pub end: T32,
+    pub weight: Weight,
 }
 
 impl RepeaterClamp {
@@ -14,12 +16,12 @@ impl RepeaterClamp {
     }
 }
 
-#[derive(Component)]
+#[derive(Clone, Debug, Component, Serialize, Deserialize)]
 pub struct Repeater {
-    period: P32,
-    ping_pong: bool,
-    ceil: RepeaterClamp,
-    floor: RepeaterClamp,
+    pub period: P32,
+    pub ping_pong: bool,
+    pub ceil: RepeaterClamp,
+    pub floor: RepeaterClamp,
 }
 
 impl Repeater {
@@ -56,7 +58,7 @@ pub fn produce_repetitions(
         .filter(|(.., Repeater { period, .. })| f32::EPSILON < period.raw())
         .for_each(|(offsets, coverage, Repeater { ping_pong, period, floor, ceil })| {
             coverage.iter().for_each(|index| {
-                if let Some(time) = [*seek_times[index], song_info.pos]
+                if let Some(time) = [*seek_times[index], song_info.visual_pos()]
                     .iter()
                     .find(|time| offsets.scheduled_at(**time))
                 {
@@ -3,25 +3,63 @@ use bevy::prelude::*;
 
 use derive_more::From;
 use noisy_float::prelude::*;
+use serde::{Deserialize, Serialize};
 
-enum PressKind {
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PressKind {
     Press(P32),
     Hold(P32, P32),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
-enum PressStrength {
+pub enum PressStrength {
     Single = 1,
     Double = 2,
     Triple = 3,
 }
 
+/// Seconds either side of the start of a prompt in which a hit judges it
+pub const JUDGEMENT_WINDOW: f32 = 0.15;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hitsound {
+    /// File name of a sample in the hitsounds directory of the chart
+    pub sample: String,
+    pub volume: T32,
+}
+
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
 pub struct HitPrompt {
-    offsets: TemporalOffsets,
-    press_kind: PressKind,
-    press_strength: PressStrength,
-    press_phat_key: bool,
-    signal_layer: u8,
+    pub offsets: TemporalOffsets,
+    pub press_kind: PressKind,
+    pub press_strength: PressStrength,
+    pub press_phat_key: bool,
+    pub signal_layer: u8,
+    /// Played when the prompt is judged
+    pub hitsound: Option<Hitsound>,
+}
+
+impl HitPrompt {
+    /// Single strength prompt which is scheduled for as long as it has to be pressed
+    pub fn new(press_kind: PressKind, signal_layer: u8) -> Self {
+        let (start, end) = match press_kind {
+            PressKind::Press(at) => (at, at),
+            PressKind::Hold(start, end) => (start, end.max(start)),
+        };
+
+        Self {
+            offsets: TemporalOffsets {
+                start,
+                duration: end - start,
+            },
+            press_kind,
+            press_strength: PressStrength::Single,
+            press_phat_key: false,
+            signal_layer,
+            hitsound: None,
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -35,7 +73,43 @@ pub struct HitInfo {
 #[derive(Default, Deref, DerefMut, From, Resource)]
 pub struct HitRegister(pub [Option<HitInfo>; 4]);
 
-#[derive(Debug, Component)]
+/// Keys which register hits on the layer of their slot
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Deref, DerefMut)]
+pub struct HitBindings(pub [KeyCode; 4]);
+
+impl Default for HitBindings {
+    fn default() -> Self {
+        Self([KeyCode::D, KeyCode::F, KeyCode::J, KeyCode::K])
+    }
+}
+
+/// Hits are registered at the position in the chart that is heard
+pub fn register_hits(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<HitBindings>,
+    song_info: Res<SongInfo>,
+    mut hits: ResMut<HitRegister>,
+) {
+    hits.iter_mut()
+        .zip(bindings.iter())
+        .enumerate()
+        .for_each(|(layer, (hit, key))| {
+            *hit = keys.just_pressed(*key).then_some(HitInfo {
+                object_time: song_info.pos,
+                hit_time: song_info.pos,
+                layer: layer as u8,
+            })
+        });
+}
+
+/// Seconds from the start of a prompt to the hit which judged it. Negative when early.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct Judgement(pub R32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PromptJudged(pub Entity);
+
+#[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
 pub enum ResponseKind {
     Nil,
     /// Stays at 0 state until hit, once hit which it will commece from the current time.
@@ -50,7 +124,7 @@ pub enum ResponseKind {
     Follow(P32),
 }
 
-#[derive(Component)]
+#[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
 pub struct Response {
     pub kind: ResponseKind,
     pub layer: u8,
@@ -60,12 +134,75 @@ pub struct Response {
 pub enum ResponseState {
     None,
     Hit(P32),
-    Active(bool),
+    /// Time of the hit which activated the response. Later toggles keep it.
+    Active(bool, P32),
 }
 
 #[derive(Default, Debug, PartialEq, Eq, From, Deref, DerefMut, Clone, Copy)]
 pub struct Delegated(pub bool);
 
+/// Hits after the current time haven't happened yet after seeking backwards
+#[rustfmt::skip]
+pub fn rewind_responses(
+    song_info: Res<SongInfo>,
+    mut last_pos: Local<P32>,
+    mut responses: Query<&mut ResponseState>,
+) {
+    if song_info.pos < *last_pos {
+        responses.iter_mut().for_each(|mut state| match *state {
+            ResponseState::Hit(time) if song_info.pos < time => *state = ResponseState::None,
+            ResponseState::Active(_, time) if song_info.pos < time => *state = ResponseState::None,
+            _ => {}
+        });
+    }
+
+    *last_pos = song_info.pos;
+}
+
+/// Each hit judges the closest unjudged prompt of its layer within the judgement window
+pub fn judge_prompts(
+    mut commands: Commands,
+    hits: Res<HitRegister>,
+    prompts: Query<(Entity, &HitPrompt), Without<Judgement>>,
+    mut judged_events: EventWriter<PromptJudged>,
+) {
+    let mut judged = Vec::new();
+
+    hits.iter().flatten().for_each(|hit| {
+        let closest = prompts
+            .iter()
+            .filter(|(entity, prompt)| prompt.signal_layer == hit.layer && !judged.contains(entity))
+            .map(|(entity, prompt)| (entity, hit.hit_time.raw() - prompt.offsets.start.raw()))
+            .filter(|(_, error)| error.abs() <= JUDGEMENT_WINDOW)
+            .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()));
+
+        if let Some((entity, error)) = closest {
+            commands.entity(entity).insert(Judgement(r32(error)));
+            judged_events.send(PromptJudged(entity));
+            judged.push(entity);
+        }
+    });
+}
+
+/// Prompts after the current time haven't been judged yet after seeking backwards
+pub fn rewind_judgements(
+    mut commands: Commands,
+    song_info: Res<SongInfo>,
+    mut last_pos: Local<P32>,
+    judgements: Query<(Entity, &HitPrompt), With<Judgement>>,
+) {
+    if song_info.pos < *last_pos {
+        judgements
+            .iter()
+            .filter(|(_, prompt)| song_info.pos < prompt.offsets.start)
+            .for_each(|(entity, _)| {
+                commands.entity(entity).remove::<Judgement>();
+            });
+    }
+
+    *last_pos = song_info.pos;
+}
+
 #[rustfmt::skip]
 pub fn respond_to_hits(
     hits: Res<HitRegister>,
@@ -79,12 +216,14 @@ pub fn respond_to_hits(
         &mut ResponseState
     )>,
 ) {
-    seek_times.fill_with(|| SeekTime(song_info.pos));
+    let pos = song_info.visual_pos();
+
+    seek_times.fill_with(|| SeekTime(pos));
     delegations.fill_with(|| Delegated(false));
 
     responses
         .iter_mut()
-        .filter(|(offsets, ..)| offsets.playable_at(song_info.pos))
+        .filter(|(offsets, ..)| offsets.playable_at(pos))
         .for_each(|(offsets, coverage, Response { kind, layer }, mut state)| {
             use ResponseKind::*;
             use ResponseState::*;
@@ -93,20 +232,21 @@ pub fn respond_to_hits(
                 .flatten()
                 .filter(|hit| offsets.scheduled_at(hit.hit_time) && hit.layer == *layer)
                 .for_each(|hit| match (kind, &mut *state) {
-                    (Toggle, Active(active)) => *active = !*active,
+                    (Toggle, Active(active, _)) => *active = !*active,
                     (Follow(_), last_hit) => *last_hit = Hit(hit.object_time),
-                    (Commence | Switch | Toggle, state) => *state = Active(true),
+                    (Commence | Switch, Active(..)) => {}
+                    (Commence | Switch | Toggle, state) => *state = Active(true, hit.object_time),
                     _ => {}
                 });
 
             let adjusted_offset = match (kind, &*state) {
-                (Commence, Active(active)) if !active => offsets.start,
-                (Follow(ex), &Hit(hit)) if !(hit..hit + ex).contains(&song_info.pos) => hit + ex,
-                _ => song_info.pos
+                (Commence, Active(active, _)) if !active => offsets.start,
+                (Follow(ex), &Hit(hit)) if !(hit..hit + ex).contains(&pos) => hit + ex,
+                _ => pos
             };
 
             let delegation = match (kind, &mut *state) {
-                (Switch | Toggle, Active(active)) => *active,
+                (Switch | Toggle, Active(active, _)) => *active,
                 _ => false
             };
 
@@ -126,7 +266,7 @@ mod tests {
     #[rustfmt::skip]
     #[test_case(300., 3, ResponseState::None; "wrong layer")]
     #[test_case(1100., 0, ResponseState::None; "wrong scheduling")]
-    #[test_case(300., 0, ResponseState::Active(true); "correct layer and scheduling")]
+    #[test_case(300., 0, ResponseState::Active(true, p32(300.)); "correct layer and scheduling")]
     fn hit_layers_and_scheduling(time: f32, layer: u8, expected: ResponseState) {
         let mut game = App::new();
         game.add_system(respond_to_hits);
@@ -251,4 +391,76 @@ mod tests {
             [false, true, false, false].map(Delegated)
         );
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn rewind_after_seeking_backwards() {
+        let mut game = App::new();
+        game.add_system(rewind_responses);
+
+        let entities = [
+            ResponseState::Hit(p32(300.)),
+            ResponseState::Hit(p32(100.)),
+            ResponseState::Active(true, p32(100.)),
+            ResponseState::Active(false, p32(50.)),
+            ResponseState::Active(true, p32(300.)),
+        ]
+        .map(|state| game.world.spawn(state).id());
+
+        game.insert_resource(SongInfo { pos: p32(400.), ..default() });
+        game.update();
+
+        // Moving forwards keeps the states
+        game.insert_resource(SongInfo { pos: p32(450.), ..default() });
+        game.update();
+        assert_eq!(game.world.get::<ResponseState>(entities[0]), Some(&ResponseState::Hit(p32(300.))));
+
+        // Only hits after the new position are undone
+        game.insert_resource(SongInfo { pos: p32(150.), ..default() });
+        game.update();
+        assert_eq!(
+            entities.map(|entity| game.world.get::<ResponseState>(entity).unwrap()),
+            [
+                &ResponseState::None,
+                &ResponseState::Hit(p32(100.)),
+                &ResponseState::Active(true, p32(100.)),
+                &ResponseState::Active(false, p32(50.)),
+                &ResponseState::None,
+            ]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn judge_closest_prompts() {
+        let mut game = App::new();
+        game.add_event::<PromptJudged>()
+            .add_systems((rewind_judgements, judge_prompts).chain());
+
+        let prompts = [(1., 0), (1.1, 0), (1.05, 1), (2., 0)]
+            .map(|(at, layer)| game.world.spawn(HitPrompt::new(PressKind::Press(p32(at)), layer)).id());
+
+        let hit = |time: f32, layer| Some(HitInfo { object_time: p32(time), hit_time: p32(time), layer });
+
+        // The second hit can't judge the prompt judged by the first
+        game.insert_resource(SongInfo { pos: p32(1.), ..default() })
+            .insert_resource(HitRegister([hit(1.02, 0), hit(1.04, 0), hit(1.5, 1), None]));
+        game.update();
+
+        let errors = prompts.map(|prompt| game.world.get::<Judgement>(prompt).map(|judgement| judgement.0.raw()));
+        assert!(matches!(errors, [Some(early), Some(late), None, None] if
+            (early - 0.02).abs() < 0.0001 && (late + 0.06).abs() < 0.0001
+        ));
+        assert_eq!(game.world.resource::<Events<PromptJudged>>().len(), 2);
+
+        // Seeking back before a judged prompt undoes its judgement
+        game.insert_resource(SongInfo { pos: p32(1.2), ..default() })
+            .insert_resource(HitRegister::default());
+        game.update();
+
+        game.insert_resource(SongInfo { pos: p32(1.08), ..default() });
+        game.update();
+        assert!(game.world.get::<Judgement>(prompts[0]).is_some());
+        assert!(game.world.get::<Judgement>(prompts[1]).is_none());
+    }
 }
@@ -10,6 +10,7 @@ use bevy::{
         clear_color::ClearColorConfig,
     },
     prelude::*,
+    render::camera::ScalingMode,
     window::{PresentMode, WindowResolution},
 };
 use noisy_float::prelude::*;
@@ -19,30 +20,26 @@ use bevy_screen_diagnostics::{ScreenDiagnosticsPlugin, ScreenFrameDiagnosticsPlu
 
 mod audio;
 mod automation;
+mod calibration;
 mod editor;
 mod harmonizer;
 mod hit;
+mod play_field;
 mod serialization;
+mod settings;
 mod silhouettes;
 mod timing;
 mod utils;
 
 use audio::*;
+use calibration::CalibrationPlugin;
 use editor::*;
 use harmonizer::HarmonizerPlugin;
+use play_field::{PlayFieldPlugin, PLAY_FIELD};
+use serialization::SerializationPlugin;
+use settings::{Settings, SettingsPlugin};
 use silhouettes::*;
 
-#[derive(Resource)]
-struct Settings {
-    ui_scale: f32,
-}
-
-impl Default for Settings {
-    fn default() -> Self {
-        Self { ui_scale: 1. }
-    }
-}
-
 #[derive(Debug, Clone, Eq, PartialEq, Default, Hash, States)]
 enum GameState {
     #[cfg(not(debug_assertions))]
@@ -53,6 +50,7 @@ enum GameState {
     Edit,
     Play,
     Paused,
+    Calibrate,
 }
 
 fn map_selected(game_state: Res<State<GameState>>) -> bool {
@@ -74,17 +72,21 @@ fn setup(world: &mut World) {
         Camera2dBundle {
             camera: Camera { hdr: true, ..default() },
             camera_2d: Camera2d { clear_color: ClearColorConfig::Custom(Color::rgb(0., 0., 0.)) },
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::Fixed { width: PLAY_FIELD.x, height: PLAY_FIELD.y },
+                ..default()
+            },
             ..default()
         },
     ));
 
     *world.query::<&mut Window>().get_single_mut(world).unwrap() = Window {
         title: "Rhythm Engine".into(),
-        resolution: WindowResolution::new(1920., 1080.).with_scale_factor_override(1.),
+        resolution: WindowResolution::new(PLAY_FIELD.x, PLAY_FIELD.y),
         present_mode: PresentMode::AutoVsync,
         prevent_default_event_handling: false,
         fit_canvas_to_parent: true,
-        resizable: false,
+        resizable: true,
         ..default()
     };
 }
@@ -108,7 +110,10 @@ fn main() {
         .add_plugin(HarmonizerPlugin)
         .add_plugin(SilhouettePlugin)
         .add_plugin(EditorPlugin)
-        .init_resource::<Settings>()
+        .add_plugin(SerializationPlugin)
+        .add_plugin(CalibrationPlugin)
+        .add_plugin(PlayFieldPlugin)
+        .add_plugin(SettingsPlugin)
         .add_startup_system(setup);
 
     #[cfg(debug_assertions)]
@@ -0,0 +1,103 @@
+use crate::{utils::*, GameState, Settings};
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+
+/// World units of the play field. Charts are authored in these whatever the size of the window.
+pub const PLAY_FIELD: Vec2 = Vec2::new(1920., 1080.);
+
+/// Area of the window the play field is fitted into in Edit
+pub struct PlayField;
+
+/// Largest area with the aspect ratio of the play field centered in an area
+pub fn letterbox(min: Vec2, size: Vec2) -> (Vec2, Vec2) {
+    let fitted = PLAY_FIELD * (size / PLAY_FIELD).min_element().max(0.);
+    (min + (size - fitted) / 2., fitted)
+}
+
+/// [`Realestate`] is in egui points so it's scaled by the UI scale and the scale factor of
+/// the window to get physical pixels
+fn fit_play_field(
+    state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    realestate: Res<Realestate<PlayField>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<&mut Camera, With<Camera2d>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    let physical = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+
+    let (min, size) = match state.0 {
+        GameState::Edit => {
+            let points = window.scale_factor() as f32 * settings.ui_scale;
+            let (pos, size) = (realestate.pos(), realestate.size());
+            (
+                Vec2::new(pos.x, pos.y) * points,
+                Vec2::new(size.x, size.y) * points,
+            )
+        }
+        _ => (Vec2::ZERO, physical),
+    };
+
+    let (min, size) = letterbox(min, size);
+    let min = min
+        .round()
+        .clamp(Vec2::ZERO, (physical - 1.).max(Vec2::ZERO));
+    let size = size
+        .round()
+        .clamp(Vec2::ONE, (physical - min).max(Vec2::ONE));
+    let (position, size) = (min.as_uvec2(), size.as_uvec2());
+
+    cameras
+        .iter_mut()
+        .filter(|camera| {
+            camera
+                .viewport
+                .as_ref()
+                .map(|viewport| (viewport.physical_position, viewport.physical_size))
+                != Some((position, size))
+        })
+        .for_each(|mut camera| {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            })
+        });
+}
+
+pub struct PlayFieldPlugin;
+
+impl Plugin for PlayFieldPlugin {
+    fn build(&self, game: &mut App) {
+        game.init_resource::<Realestate<PlayField>>()
+            .add_system(fit_play_field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn letterboxes() {
+        assert_eq!(
+            letterbox(Vec2::ZERO, Vec2::new(1280., 720.)),
+            (Vec2::ZERO, Vec2::new(1280., 720.))
+        );
+        assert_eq!(
+            letterbox(Vec2::ZERO, Vec2::new(1920., 1200.)),
+            (Vec2::new(0., 60.), Vec2::new(1920., 1080.))
+        );
+        assert_eq!(
+            letterbox(Vec2::new(100., 0.), Vec2::new(1000., 270.)),
+            (Vec2::new(360., 0.), Vec2::new(480., 270.))
+        );
+        assert_eq!(letterbox(Vec2::ZERO, Vec2::ZERO), (Vec2::ZERO, Vec2::ZERO));
+    }
+}
@@ -1,7 +1,654 @@
-use bevy::prelude::*;
-use std::path::{Path, PathBuf};
+pub mod osu;
 
-#[derive(Resource)]
-struct MapSelection(PathBuf);
+use crate::{
+    audio::{ChartLoadEvent, SongLoadEvent},
+    automation::{reactive::*, sequence::*, spline::*, *},
+    harmonizer::{arranger::*, channels::*, repeater::*},
+    hit::*,
+    silhouettes::CloudRecord,
+    timing::*,
+    utils::*,
+};
 
-fn load(map: Res<MapSelection>) {}
+use bevy::{
+    asset::FileAssetIo,
+    ecs::{query::ReadOnlyWorldQuery, world::EntityMut},
+    prelude::*,
+};
+use noisy_float::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use tap::{Pipe, Tap};
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+pub const CHART_FILE: &str = "chart.bin";
+/// Human readable chart which is meant to be version controlled
+pub const CHART_TEXT_FILE: &str = "chart.ron";
+/// Audio file of charts which don't name one
+pub const SONG_FILE: &str = "song.ogg";
+/// Audio formats which can be decoded
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["ogg", "mp3", "wav", "flac"];
+
+/// Directory which holds the song and chart file of a chart
+pub fn chart_dir(chart_id: &str) -> PathBuf {
+    FileAssetIo::get_base_path()
+        .tap_mut(|path| path.push("assets"))
+        .tap_mut(|path| path.push("charts"))
+        .tap_mut(|path| path.push(chart_id))
+}
+
+#[derive(Debug)]
+pub enum ChartError {
+    Io(io::Error),
+    Binary(bincode::Error),
+    Text(ron::Error),
+    /// Text charts report where they could not be parsed
+    TextParse(ron::error::SpannedError),
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Binary(error) => write!(f, "{error}"),
+            Self::Text(error) => write!(f, "{error}"),
+            Self::TextParse(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for ChartError {}
+
+impl From<io::Error> for ChartError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<bincode::Error> for ChartError {
+    fn from(error: bincode::Error) -> Self {
+        Self::Binary(error)
+    }
+}
+
+impl From<ron::Error> for ChartError {
+    fn from(error: ron::Error) -> Self {
+        Self::Text(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for ChartError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::TextParse(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartMeta {
+    pub title: String,
+    pub artist: String,
+    /// Name of the difficulty
+    pub version: String,
+    /// Audio file in the chart directory
+    pub audio: String,
+    /// Milliseconds. Positive offsets delay the chart relative to the audio.
+    pub audio_offset: f32,
+}
+
+impl Default for ChartMeta {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            artist: String::new(),
+            version: String::new(),
+            audio: SONG_FILE.into(),
+            audio_offset: 0.,
+        }
+    }
+}
+
+/// Indices into the sources of a chart which are resolved to entities on spawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceIndices {
+    pub main: usize,
+    pub delegation: Option<usize>,
+}
+
+impl SourceIndices {
+    fn resolve<T>(&self, entities: &[Entity]) -> Sources<T> {
+        Sources {
+            main: entities[self.main].into(),
+            delegation: self.delegation.map(|index| entities[index].into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceKind {
+    Spline,
+    RGBA,
+    Luminosity,
+    Scale,
+    Rotation,
+}
+
+/// Indices into the sequences of one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClipSequences {
+    pub kind: SequenceKind,
+    pub primary: SourceIndices,
+    pub secondary: Option<SourceIndices>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clip {
+    pub offsets: TemporalOffsets,
+    pub coverage: Vec<CoverageRange>,
+    /// Index into the automations of the chart
+    pub automation: Option<SourceIndices>,
+    pub sequences: Option<ClipSequences>,
+    pub response: Option<Response>,
+    pub repeater: Option<Repeater>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Sequences {
+    pub splines: Vec<Sequence<Spline>>,
+    pub colors: Vec<Sequence<RGBA>>,
+    pub luminosities: Vec<Sequence<Luminosity>>,
+    pub scales: Vec<Sequence<Scale>>,
+    pub rotations: Vec<Sequence<Rotation>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum AutomationSource {
+    Anchors {
+        automation: Automation<T32>,
+        interpolation: Interpolation,
+    },
+    Audio(AudioReactive),
+}
+
+#[rustfmt::skip]
+impl AutomationSource {
+    fn spawn(self, world: &mut World) -> Entity {
+        match self {
+            Self::Anchors { automation, interpolation } => world.spawn((automation, interpolation)),
+            Self::Audio(reactive) => world.spawn(reactive),
+        }
+        .id()
+    }
+
+    fn extract(world: &World, entity: Entity) -> Option<Self> {
+        match (world.get::<Automation<T32>>(entity), world.get::<AudioReactive>(entity)) {
+            (_, Some(reactive)) => Some(Self::Audio(reactive.clone())),
+            (Some(automation), None) => Some(Self::Anchors {
+                automation: automation.clone(),
+                interpolation: world.get::<Interpolation>(entity).copied().unwrap_or_default(),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Chart {
+    pub meta: ChartMeta,
+    pub tempo: TempoMap,
+    #[serde(default)]
+    pub channels: Channels,
+    pub prompts: Vec<HitPrompt>,
+    pub sequences: Sequences,
+    pub automations: Vec<AutomationSource>,
+    pub clips: Vec<Clip>,
+    pub clouds: Vec<CloudRecord>,
+}
+
+/// Order in which sources are first referenced by clips
+#[derive(Default)]
+struct SourceOrder(Vec<Entity>);
+
+impl SourceOrder {
+    fn index(&mut self, entity: Entity) -> usize {
+        self.0
+            .iter()
+            .position(|found| *found == entity)
+            .unwrap_or_else(|| self.0.len().tap(|_| self.0.push(entity)))
+    }
+
+    fn indices<T>(&mut self, sources: &Sources<T>) -> SourceIndices {
+        SourceIndices {
+            main: self.index(*sources.main),
+            delegation: sources.delegation.map(|delegation| self.index(*delegation)),
+        }
+    }
+
+    /// Sources which aren't referenced by any clip are kept after the referenced ones sorted
+    /// by their text
+    #[rustfmt::skip]
+    fn entities<F: ReadOnlyWorldQuery>(
+        mut self,
+        world: &mut World,
+        text: impl Fn(&World, Entity) -> String,
+    ) -> Vec<Entity> {
+        let mut unreferenced = world
+            .query_filtered::<Entity, F>()
+            .iter(world)
+            .filter(|entity| !self.0.contains(entity))
+            .collect::<Vec<_>>();
+
+        unreferenced.sort_by_cached_key(|entity| text(world, *entity));
+        self.0.extend(unreferenced);
+        self.0
+    }
+
+    fn collect<T: Component + Clone + Serialize>(self, world: &mut World) -> Vec<T> {
+        self.entities::<With<T>>(world, |world, entity| text_key(&world.get::<T>(entity)))
+            .iter()
+            .flat_map(|entity| world.get::<T>(*entity))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Breaks ties between otherwise equal sort keys so extraction doesn't depend on query order
+fn text_key(value: &impl Serialize) -> String {
+    ron::to_string(value).unwrap_or_default()
+}
+
+/// What a clip plays so clips which share their offsets and coverage have a stable order
+#[rustfmt::skip]
+fn clip_text(world: &World, entity: Entity) -> String {
+    let automations = world.get::<Sources<Automation<T32>>>(entity).map(|sources| {
+        [Some(sources.main), sources.delegation].map(|source| source
+            .and_then(|source| AutomationSource::extract(world, *source))
+        )
+    });
+
+    text_key(&(
+        world.get::<Response>(entity),
+        world.get::<Repeater>(entity),
+        automations,
+        sequences_text::<Spline>(world, entity),
+        sequences_text::<RGBA>(world, entity),
+        sequences_text::<Luminosity>(world, entity),
+        sequences_text::<Scale>(world, entity),
+        sequences_text::<Rotation>(world, entity),
+    ))
+}
+
+#[rustfmt::skip]
+fn sequences_text<T: Default + Send + Sync + 'static>(world: &World, entity: Entity) -> Option<String>
+where
+    Sequence<T>: Serialize,
+{
+    let sequences = |sources: &Sources<Sequence<T>>| {
+        [Some(sources.main), sources.delegation].map(|source| source
+            .and_then(|source| world.get::<Sequence<T>>(*source))
+        )
+    };
+
+    world.get::<PrimarySequence<Sources<Sequence<T>>>>(entity).map(|primary| text_key(&(
+        sequences(primary),
+        world
+            .get::<SecondarySequence<Sources<Sequence<T>>>>(entity)
+            .map(|secondary| sequences(secondary)),
+    )))
+}
+
+fn spawn_sources<T: Component>(world: &mut World, sources: Vec<T>) -> Vec<Entity> {
+    sources
+        .into_iter()
+        .map(|source| world.spawn(source).id())
+        .collect()
+}
+
+#[rustfmt::skip]
+fn insert_sequences<T: Default + Send + Sync + 'static>(
+    entity: &mut EntityMut,
+    sequences: &ClipSequences,
+    sources: &[Entity],
+) {
+    entity.insert(PrimarySequence(sequences.primary.resolve::<Sequence<T>>(sources)));
+
+    if let Some(secondary) = sequences.secondary {
+        entity.insert(SecondarySequence(secondary.resolve::<Sequence<T>>(sources)));
+    }
+}
+
+#[rustfmt::skip]
+fn extract_sequences<T: Default + Send + Sync + 'static>(
+    world: &World,
+    entity: Entity,
+    kind: SequenceKind,
+    order: &mut SourceOrder,
+) -> Option<ClipSequences> {
+    world.get::<PrimarySequence<Sources<Sequence<T>>>>(entity).map(|primary| ClipSequences {
+        kind,
+        primary: order.indices(primary),
+        secondary: world
+            .get::<SecondarySequence<Sources<Sequence<T>>>>(entity)
+            .map(|secondary| order.indices(secondary)),
+    })
+}
+
+impl Chart {
+    pub fn save(&self, dir: &Path) -> Result<(), ChartError> {
+        File::create(dir.join(CHART_FILE))?
+            .pipe(BufWriter::new)
+            .pipe(|writer| bincode::serialize_into(writer, self))
+            .map_err(ChartError::from)
+    }
+
+    /// Text output only depends on the chart so saving the same chart is byte identical
+    pub fn save_text(&self, dir: &Path) -> Result<(), ChartError> {
+        fs::write(dir.join(CHART_TEXT_FILE), self.to_text()?).map_err(ChartError::from)
+    }
+
+    /// Prefers the text chart over the binary chart when both are present
+    pub fn load(dir: &Path) -> Result<Self, ChartError> {
+        match dir.join(CHART_TEXT_FILE) {
+            path if path.exists() => fs::read_to_string(path)?.pipe_deref(Self::from_text),
+            _ => File::open(dir.join(CHART_FILE))?
+                .pipe(BufReader::new)
+                .pipe(bincode::deserialize_from)
+                .map_err(ChartError::from),
+        }
+    }
+
+    pub fn to_text(&self) -> Result<String, ChartError> {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map(|text| text + "\n")
+            .map_err(ChartError::from)
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, ChartError> {
+        ron::from_str(text).map_err(ChartError::from)
+    }
+
+    #[rustfmt::skip]
+    pub fn spawn(self, world: &mut World) {
+        let Chart { meta, tempo, channels, prompts, sequences, automations, clips, clouds } = self;
+
+        world.insert_resource(meta);
+        world.insert_resource(tempo);
+        world.insert_resource(channels);
+        world.spawn_batch(prompts.into_iter().map(|prompt| (prompt.offsets.clone(), prompt)));
+
+        let Sequences { splines, colors, luminosities, scales, rotations } = sequences;
+        let sequences = [
+            spawn_sources(world, splines),
+            spawn_sources(world, colors),
+            spawn_sources(world, luminosities),
+            spawn_sources(world, scales),
+            spawn_sources(world, rotations),
+        ];
+
+        let automations = automations
+            .into_iter()
+            .map(|source| source.spawn(world))
+            .collect::<Vec<_>>();
+
+        clips.into_iter().for_each(|clip| {
+            let mut entity = world.spawn((clip.offsets, ChannelCoverage(clip.coverage.into())));
+
+            if let Some(automation) = clip.automation {
+                entity.insert(automation.resolve::<Automation<T32>>(&automations));
+            }
+            if let Some(clip_sequences) = clip.sequences {
+                let sources = &sequences[clip_sequences.kind as usize];
+
+                match clip_sequences.kind {
+                    SequenceKind::Spline => insert_sequences::<Spline>(&mut entity, &clip_sequences, sources),
+                    SequenceKind::RGBA => insert_sequences::<RGBA>(&mut entity, &clip_sequences, sources),
+                    SequenceKind::Luminosity => insert_sequences::<Luminosity>(&mut entity, &clip_sequences, sources),
+                    SequenceKind::Scale => insert_sequences::<Scale>(&mut entity, &clip_sequences, sources),
+                    SequenceKind::Rotation => insert_sequences::<Rotation>(&mut entity, &clip_sequences, sources),
+                }
+            }
+            if let Some(response) = clip.response {
+                entity.insert((response, ResponseState::None));
+            }
+            if let Some(repeater) = clip.repeater {
+                entity.insert(repeater);
+            }
+        });
+
+        clouds.into_iter().for_each(|cloud| { cloud.spawn(world); });
+    }
+
+    /// Prompts, clips and clouds are sorted by time then by their text. Sources are ordered by
+    /// when they are first referenced.
+    #[rustfmt::skip]
+    pub fn extract(world: &mut World) -> Self {
+        let mut prompts = world
+            .query::<&HitPrompt>()
+            .iter(world)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        prompts.sort_by_cached_key(|prompt| (prompt.offsets.start, prompt.signal_layer, text_key(prompt)));
+
+        let mut clip_entities = world
+            .query::<(Entity, &TemporalOffsets, &ChannelCoverage)>()
+            .iter(world)
+            .map(|(entity, offsets, coverage)| (entity, offsets.clone(), coverage.to_vec()))
+            .collect::<Vec<_>>();
+
+        clip_entities.sort_by_cached_key(|(entity, offsets, coverage)| {
+            (offsets.start, offsets.duration, coverage.clone(), clip_text(world, *entity))
+        });
+
+        let mut automation_order = SourceOrder::default();
+        let mut sequence_orders = [(); 5].map(|_| SourceOrder::default());
+
+        let clips = clip_entities
+            .into_iter()
+            .map(|(entity, offsets, coverage)| Clip {
+                offsets,
+                coverage,
+                automation: world
+                    .get::<Sources<Automation<T32>>>(entity)
+                    .map(|sources| automation_order.indices(sources)),
+                sequences: [
+                    SequenceKind::Spline,
+                    SequenceKind::RGBA,
+                    SequenceKind::Luminosity,
+                    SequenceKind::Scale,
+                    SequenceKind::Rotation,
+                ]
+                .into_iter()
+                .find_map(|kind| {
+                    let order = &mut sequence_orders[kind as usize];
+
+                    match kind {
+                        SequenceKind::Spline => extract_sequences::<Spline>(world, entity, kind, order),
+                        SequenceKind::RGBA => extract_sequences::<RGBA>(world, entity, kind, order),
+                        SequenceKind::Luminosity => extract_sequences::<Luminosity>(world, entity, kind, order),
+                        SequenceKind::Scale => extract_sequences::<Scale>(world, entity, kind, order),
+                        SequenceKind::Rotation => extract_sequences::<Rotation>(world, entity, kind, order),
+                    }
+                }),
+                response: world.get::<Response>(entity).copied(),
+                repeater: world.get::<Repeater>(entity).cloned(),
+            })
+            .collect::<Vec<_>>();
+
+        let [splines, colors, luminosities, scales, rotations] = sequence_orders;
+
+        Chart {
+            meta: world.get_resource::<ChartMeta>().cloned().unwrap_or_default(),
+            tempo: world.get_resource::<TempoMap>().cloned().unwrap_or_default(),
+            channels: world.get_resource::<Channels>().cloned().unwrap_or_default(),
+            prompts,
+            sequences: Sequences {
+                splines: splines.collect(world),
+                colors: colors.collect(world),
+                luminosities: luminosities.collect(world),
+                scales: scales.collect(world),
+                rotations: rotations.collect(world),
+            },
+            automations: automation_order
+                .entities::<Or<(With<Automation<T32>>, With<AudioReactive>)>>(world, |world, entity| {
+                    text_key(&AutomationSource::extract(world, entity))
+                })
+                .into_iter()
+                .flat_map(|entity| AutomationSource::extract(world, entity))
+                .collect(),
+            clips,
+            clouds: CloudRecord::extract(world),
+        }
+    }
+}
+
+/// Charts without a chart file still play their song
+pub fn load_chart(
+    mut commands: Commands,
+    mut chart_load_events: EventReader<ChartLoadEvent>,
+    mut song_load_events: EventWriter<SongLoadEvent>,
+) {
+    let Some(ChartLoadEvent {
+        chart_id,
+        start_from,
+    }) = chart_load_events.iter().last()
+    else {
+        return;
+    };
+
+    let dir = chart_dir(chart_id);
+
+    // Mutes and solos only apply to the chart they were set on
+    commands.insert_resource(Table::<ChannelMix>::default());
+
+    let meta = match Chart::load(&dir) {
+        Ok(chart) => chart
+            .meta
+            .clone()
+            .tap(|_| commands.add(|world: &mut World| chart.spawn(world))),
+        Err(error) => {
+            warn!("Could not load chart {chart_id}: {error}");
+            commands.insert_resource(Channels::default());
+            ChartMeta::default().tap(|meta| commands.insert_resource(meta.clone()))
+        }
+    };
+
+    song_load_events.send(SongLoadEvent {
+        path: dir.join(&meta.audio),
+        title: match meta.title.is_empty() {
+            true => chart_id.clone(),
+            false => meta.title,
+        },
+        start_from: *start_from,
+        chart_offset: r32(meta.audio_offset / 1000.),
+    });
+}
+
+#[derive(Default, Debug)]
+pub struct ChartSaveEvent {
+    pub chart_id: String,
+}
+
+/// Writes both the binary and text chart
+pub fn save_chart(world: &mut World) {
+    let Some(ChartSaveEvent { chart_id }) = world
+        .resource_mut::<Events<ChartSaveEvent>>()
+        .drain()
+        .last()
+    else {
+        return;
+    };
+
+    let dir = chart_dir(&chart_id);
+    let chart = Chart::extract(world);
+
+    if let Err(error) = fs::create_dir_all(&dir)
+        .map_err(ChartError::from)
+        .and_then(|_| chart.save(&dir))
+        .and_then(|_| chart.save_text(&dir))
+    {
+        error!("Could not save chart {chart_id}: {error}");
+    }
+}
+
+pub struct SerializationPlugin;
+
+impl Plugin for SerializationPlugin {
+    fn build(&self, game: &mut App) {
+        game.init_resource::<TempoMap>()
+            .init_resource::<ChartMeta>()
+            .add_event::<ChartSaveEvent>()
+            .add_system(load_chart)
+            .add_system(save_chart)
+            // Imported charts replace any chart loaded during startup
+            .add_startup_system(osu::import_from_args.in_base_set(StartupSet::PostStartup));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SAMPLE: &str = include_str!("fixtures/sample.ron");
+
+    #[test]
+    fn text_round_trip() {
+        let chart = Chart::from_text(SAMPLE).unwrap();
+
+        assert_eq!(chart.to_text().unwrap(), SAMPLE);
+        assert_eq!(chart.to_text().unwrap(), chart.to_text().unwrap());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn world_round_trip() {
+        let mut world = World::new();
+
+        Chart::from_text(SAMPLE)
+            .unwrap()
+            .tap_mut(|chart| chart.clips.reverse())
+            .tap_mut(|chart| chart.prompts.reverse())
+            .tap_mut(|chart| chart.clouds.reverse())
+            .spawn(&mut world);
+
+        assert_eq!(world.query::<&ChannelCoverage>().iter(&world).count(), 5);
+        assert_eq!(world.query::<&Sequence<Rotation>>().iter(&world).count(), 2);
+        assert_eq!(world.query::<&ResponseState>().iter(&world).count(), 2);
+        assert!(world.query::<&Interpolation>().iter(&world).any(|interpolation| *interpolation == Interpolation::MonotoneCubic));
+
+        // Sorted regardless of the order of entities
+        assert_eq!(Chart::extract(&mut world).to_text().unwrap(), SAMPLE);
+        assert_eq!(Chart::extract(&mut world).to_text().unwrap(), SAMPLE);
+    }
+
+    #[test]
+    fn load_chart_files() {
+        let dir = std::env::temp_dir().join(format!("chart-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let chart = Chart::from_text(SAMPLE).unwrap();
+        chart.save(&dir).unwrap();
+        assert_eq!(Chart::load(&dir).unwrap().to_text().unwrap(), SAMPLE);
+
+        // Text charts take precedence over binary charts
+        chart
+            .clone()
+            .tap_mut(|chart| chart.meta.version = "Edited".into())
+            .save_text(&dir)
+            .unwrap();
+        assert_eq!(Chart::load(&dir).unwrap().meta.version, "Edited");
+
+        fs::write(dir.join(CHART_TEXT_FILE), "(meta: 0)").unwrap();
+        assert!(matches!(Chart::load(&dir), Err(ChartError::TextParse(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
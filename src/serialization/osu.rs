@@ -0,0 +1,633 @@
+//! Importer for osu! beatmaps (`.osu` files).
+//! See <https://osu.ppy.sh/wiki/en/Client/File_formats/osu_%28file_format%29>
+
+use super::*;
+use crate::play_field::PLAY_FIELD;
+
+use core::iter::once as iter_once;
+use itertools::Itertools;
+use std::{error::Error, fmt, fs, io, str::FromStr};
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Chart(ChartError),
+    /// Line numbers start from 1
+    Parse {
+        line: usize,
+        reason: String,
+    },
+    /// See [`AUDIO_EXTENSIONS`] for the audio formats which can be played
+    UnsupportedAudio(PathBuf),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Chart(error) => write!(f, "Could not save chart: {error}"),
+            Self::Parse { line, reason } => write!(f, "Line {line}: {reason}"),
+            Self::UnsupportedAudio(path) => write!(f, "Unsupported audio file {}", path.display()),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ChartError> for ImportError {
+    fn from(error: ChartError) -> Self {
+        Self::Chart(error)
+    }
+}
+
+/// Parsed beatmap along with the audio file it refers to
+pub struct Beatmap {
+    pub audio: PathBuf,
+    pub chart: Chart,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    General,
+    Metadata,
+    Difficulty,
+    TimingPoints,
+    HitObjects,
+    Other,
+}
+
+#[derive(Clone, Copy)]
+struct TimingPoint {
+    /// Milliseconds
+    time: f32,
+    /// Milliseconds per beat for uninherited points.
+    /// Negative inverse slider velocity percentage for inherited points.
+    beat_length: f32,
+    meter: u8,
+    uninherited: bool,
+}
+
+impl TimingPoint {
+    fn tempo(&self) -> Tempo {
+        // Tempos can't start before the song so negative offsets are moved to their first
+        // beat inside the song
+        let skipped = (-self.time / self.beat_length).ceil().max(0.);
+
+        Tempo {
+            offset: p32((self.time + skipped * self.beat_length) / 1000.),
+            bpm: p32(60000. / self.beat_length),
+            meter: self.meter,
+        }
+    }
+}
+
+fn field<T: FromStr>(
+    fields: &[&str],
+    index: usize,
+    line: usize,
+    name: &str,
+) -> Result<T, ImportError> {
+    fields
+        .get(index)
+        .and_then(|field| field.trim().parse().ok())
+        .ok_or_else(|| ImportError::Parse {
+            line,
+            reason: format!("Invalid {name}"),
+        })
+}
+
+fn seconds(millis: f32) -> P32 {
+    p32(millis.max(0.) / 1000.)
+}
+
+/// Size of the osu! playfield in osu! pixels
+const OSU_FIELD: Vec2 = Vec2::new(512., 384.);
+/// Radius of the body drawn for sliders in osu! pixels
+const SLIDER_RADIUS: f32 = 32.;
+const SLIDER_SIDES: usize = 8;
+
+/// World units per osu! pixel with the osu! playfield fitted into the play field
+fn field_scale() -> f32 {
+    (PLAY_FIELD / OSU_FIELD).min_element()
+}
+
+/// Converts osu! coordinates to world coordinates centered on the play field
+fn world_point(point: Vec2) -> Vec2 {
+    Vec2::new(point.x - OSU_FIELD.x / 2., OSU_FIELD.y / 2. - point.y) * field_scale()
+}
+
+/// Outline of a slider head which the slider cloud moves along the slider path
+fn slider_body(head: Vec2) -> Vec<Vec2> {
+    (0..SLIDER_SIDES)
+        .map(|side| Vec2::from_angle(std::f32::consts::TAU * side as f32 / SLIDER_SIDES as f32))
+        .map(|direction| world_point(head) + direction * SLIDER_RADIUS * field_scale())
+        .collect()
+}
+
+/// Converts osu! coordinates to spline coordinates relative to the head of a slider.
+/// osu! has y pointing down.
+fn local_point(head: Vec2, point: &str, line: usize) -> Result<Vec2, ImportError> {
+    point
+        .split_once(':')
+        .and_then(|(x, y)| {
+            x.trim()
+                .parse::<f32>()
+                .ok()
+                .zip(y.trim().parse::<f32>().ok())
+        })
+        .map(|(x, y)| Vec2::new(x - head.x, head.y - y))
+        .ok_or_else(|| ImportError::Parse {
+            line,
+            reason: format!("Invalid curve point {point}"),
+        })
+}
+
+/// Bezier paths are split into separate curves at repeated points
+#[rustfmt::skip]
+fn bezier_segments(points: &[Vec2]) -> Vec<Segment> {
+    let runs = points
+        .iter()
+        .skip(1)
+        .fold(vec![vec![points[0]]], |mut runs, point| {
+            match runs.last_mut().unwrap() {
+                run if 1 < run.len() && run.last() == Some(point) => runs.push(vec![*point]),
+                run => run.push(*point),
+            }
+            runs
+        });
+
+    runs.into_iter()
+        .filter(|run| 1 < run.len())
+        .map(|run| Segment {
+            position: *run.last().unwrap(),
+            curvature: match run.as_slice() {
+                [_, _] => Curvature::Linear,
+                [_, ctrl, _] => Curvature::Quadratic(*ctrl),
+                [_, a, b, _] => Curvature::Cubic(*a, *b),
+                [_, ctrls @ .., _] => Curvature::Bezier(ctrls.to_vec()),
+                _ => unreachable!(),
+            },
+        })
+        .collect()
+}
+
+/// Path of a slider starting from the origin along with the ratio of the path that is travelled.
+/// osu! clips or linearly extends paths to match the length of the slider.
+#[rustfmt::skip]
+fn slider_path(head: Vec2, curve: &str, length: f32, line: usize) -> Result<(Spline, T32), ImportError> {
+    let mut parts = curve.split('|');
+    let kind = parts.next().unwrap_or_default().trim();
+
+    let points = iter_once(Ok(Vec2::ZERO))
+        .chain(parts.map(|point| local_point(head, point, line)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let path = match (kind, points.as_slice()) {
+        (_, [_]) => vec![],
+        ("L", points) => points
+            .iter()
+            .skip(1)
+            .map(|point| Segment { curvature: Curvature::Linear, position: *point })
+            .collect(),
+        ("P", [_, ctrl, end]) => vec![Segment { curvature: Curvature::Circular(*ctrl), position: *end }],
+        ("C", [_, ctrls @ .., end]) => vec![
+            Segment { curvature: Curvature::CatmullRom(ctrls.to_vec()), position: *end }
+        ],
+        ("B" | "P", points) => bezier_segments(points),
+        _ => return Err(ImportError::Parse { line, reason: format!("Invalid curve type {kind}") }),
+    };
+
+    let mut spline = Spline { path, ..default() }.tap_mut(Spline::resample);
+    let path_length = spline.lut.last().map_or(0., |sample| sample.quantify().raw());
+
+    let ratio = match length - path_length {
+        _ if length <= 0. || path_length <= f32::EPSILON => t32(1.),
+        excess if f32::EPSILON < excess => {
+            let end = spline.play(t32(1.)) + spline.tangent(t32(1.)) * excess;
+            spline.path.push(Segment { curvature: Curvature::Linear, position: end });
+            spline.resample();
+            t32(1.)
+        }
+        _ => t32(length / path_length),
+    };
+
+    Ok((spline, ratio))
+}
+
+/// Automation which travels a slider path back and forth for each slide
+fn slide_automation(span: P32, slides: u32, ratio: T32) -> Automation<T32> {
+    (0..=slides)
+        .map(|slide| Anchor {
+            x: span * slide as f32,
+            val: if slide % 2 == 0 { t32(0.) } else { ratio },
+            ..default()
+        })
+        .collect::<Vec<_>>()
+        .pipe(Automation)
+}
+
+/// Greedily picks the first channel which is free by the start of a clip
+fn assign_channel(channels: &mut Vec<P32>, offsets: &TemporalOffsets) -> u8 {
+    let channel = channels
+        .iter()
+        .position(|free_from| *free_from <= offsets.start)
+        .unwrap_or_else(|| {
+            if channels.len() < MAX_CHANNELS {
+                channels.push(offsets.start);
+                channels.len() - 1
+            } else {
+                channels.iter().position_min().unwrap()
+            }
+        });
+
+    channels[channel] = offsets.start + offsets.duration;
+    channel as u8
+}
+
+#[rustfmt::skip]
+pub fn parse(source: &str) -> Result<Beatmap, ImportError> {
+    let mut section = Section::Other;
+    let mut audio = PathBuf::new();
+    let mut slider_multiplier = 1.4;
+    let mut timing_points = vec![];
+    let mut hit_objects = vec![];
+    let mut chart = Chart::default();
+
+    for (line, text) in source.lines().enumerate().map(|(index, text)| (index + 1, text.trim())) {
+        if text.is_empty() || text.starts_with("//") {
+            continue;
+        }
+
+        if let Some(name) = text.strip_prefix('[').and_then(|text| text.strip_suffix(']')) {
+            section = match name {
+                "General" => Section::General,
+                "Metadata" => Section::Metadata,
+                "Difficulty" => Section::Difficulty,
+                "TimingPoints" => Section::TimingPoints,
+                "HitObjects" => Section::HitObjects,
+                _ => Section::Other,
+            };
+            continue;
+        }
+
+        let pair = text.split_once(':').map(|(key, value)| (key.trim(), value.trim()));
+        let fields = text.split(',').collect::<Vec<_>>();
+
+        match (section, pair) {
+            (Section::General, Some(("AudioFilename", value))) => audio = value.into(),
+            (Section::Metadata, Some(("Title", value))) => chart.meta.title = value.into(),
+            (Section::Metadata, Some(("Artist", value))) => chart.meta.artist = value.into(),
+            (Section::Metadata, Some(("Version", value))) => chart.meta.version = value.into(),
+            (Section::Difficulty, Some(("SliderMultiplier", value))) => {
+                slider_multiplier = field(&[value], 0, line, "slider multiplier")?
+            }
+            (Section::TimingPoints, _) => {
+                let beat_length: f32 = field(&fields, 1, line, "beat length")?;
+                let uninherited = fields
+                    .get(6)
+                    .map_or(0. < beat_length, |uninherited| uninherited.trim() == "1");
+
+                if uninherited && beat_length <= 0. {
+                    return Err(ImportError::Parse { line, reason: "Non positive beat length".into() });
+                }
+
+                timing_points.push(TimingPoint {
+                    time: field(&fields, 0, line, "time")?,
+                    beat_length,
+                    meter: fields.get(2).map_or(Ok(4), |_| field(&fields, 2, line, "meter"))?,
+                    uninherited,
+                })
+            }
+            (Section::HitObjects, _) => hit_objects.push((line, fields)),
+            _ => {}
+        }
+    }
+
+    timing_points.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    chart.tempo = timing_points
+        .iter()
+        .filter(|point| point.uninherited)
+        .map(TimingPoint::tempo)
+        .collect::<Vec<_>>()
+        .pipe(TempoMap);
+
+    let first_beat_length = timing_points
+        .iter()
+        .find(|point| point.uninherited)
+        .map_or(500., |point| point.beat_length);
+
+    // Inherited points scale the slider velocity until the next uninherited point
+    let timing_at = |time: f32| timing_points
+        .iter()
+        .take_while(|point| point.time <= time)
+        .fold((first_beat_length, 1.), |(beat_length, velocity), point| match point.uninherited {
+            true => (point.beat_length, 1.),
+            false if point.beat_length < 0. => (beat_length, (-100. / point.beat_length).clamp(0.1, 10.)),
+            false => (beat_length, velocity),
+        });
+
+    let mut channels = vec![];
+
+    for (line, fields) in hit_objects {
+        let head = Vec2::new(field(&fields, 0, line, "x")?, field(&fields, 1, line, "y")?);
+        let time: f32 = field(&fields, 2, line, "time")?;
+        let kind: u8 = field(&fields, 3, line, "type")?;
+
+        let press_kind = match kind {
+            kind if kind & 1 != 0 => PressKind::Press(seconds(time)),
+            kind if kind & 2 != 0 => {
+                let curve = fields.get(5).copied().unwrap_or_default();
+                let slides = field::<u32>(&fields, 6, line, "slides")?.max(1);
+                let length = field(&fields, 7, line, "length")?;
+                let (beat_length, velocity) = timing_at(time);
+
+                let span = length / (slider_multiplier * 100. * velocity) * beat_length;
+                let (spline, ratio) = slider_path(head, curve, length, line)?;
+
+                let offsets = TemporalOffsets {
+                    start: seconds(time),
+                    duration: p32(span * slides as f32 / 1000.),
+                };
+                let channel = assign_channel(&mut channels, &offsets);
+                let indices = |index| SourceIndices { main: index, delegation: None };
+
+                chart.clips.push(Clip {
+                    offsets: offsets.clone(),
+                    coverage: vec![CoverageRange::new(channel, channel)],
+                    automation: Some(indices(chart.automations.len())),
+                    sequences: Some(ClipSequences {
+                        kind: SequenceKind::Spline,
+                        primary: indices(chart.sequences.splines.len()),
+                        secondary: None,
+                    }),
+                    response: None,
+                    repeater: None,
+                });
+                chart.automations.push(AutomationSource::Anchors {
+                    automation: slide_automation(p32(span / 1000.), slides, ratio),
+                    interpolation: default(),
+                });
+                chart.sequences.splines.push(Sequence(Automation(vec![Anchor { val: spline, ..default() }])));
+                chart.clouds.push(CloudRecord::polygon(slider_body(head), channel, r32(field_scale()), offsets.clone()));
+
+                PressKind::Hold(offsets.start, offsets.start + offsets.duration)
+            }
+            // Spinners and osu!mania holds
+            kind if kind & (8 | 128) != 0 => fields
+                .get(5)
+                .and_then(|end| end.split(':').next())
+                .and_then(|end| end.trim().parse::<f32>().ok())
+                .map(|end| PressKind::Hold(seconds(time), seconds(end.max(time))))
+                .ok_or_else(|| ImportError::Parse { line, reason: "Invalid end time".into() })?,
+            _ => return Err(ImportError::Parse { line, reason: format!("Unknown hit object type {kind}") }),
+        };
+
+        chart.prompts.push(HitPrompt::new(press_kind, 0));
+    }
+
+    Ok(Beatmap { audio, chart })
+}
+
+/// Imports a beatmap into a chart directory which can be loaded with a [`ChartLoadEvent`]
+pub fn import(osu: &Path, dir: &Path) -> Result<(), ImportError> {
+    let Beatmap { audio, mut chart } = fs::read_to_string(osu)?.pipe_deref(parse)?;
+    let audio = osu.parent().unwrap_or(Path::new("")).join(audio);
+
+    let Some(file_name) = audio
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .filter(|_| {
+            audio.extension().is_some_and(|extension| {
+                AUDIO_EXTENSIONS
+                    .iter()
+                    .any(|supported| extension.eq_ignore_ascii_case(supported))
+            })
+        })
+    else {
+        return Err(ImportError::UnsupportedAudio(audio));
+    };
+
+    chart.meta.audio = file_name.into();
+    fs::create_dir_all(dir)?;
+    fs::copy(&audio, dir.join(file_name))?;
+    chart.save(dir)?;
+
+    Ok(())
+}
+
+/// Beatmaps passed with `--import-osu <path>`
+fn import_args(args: impl IntoIterator<Item = String>) -> Vec<PathBuf> {
+    args.into_iter()
+        .tuple_windows()
+        .filter(|(flag, _)| flag == "--import-osu")
+        .map(|(_, path)| PathBuf::from(path))
+        .collect()
+}
+
+/// Imports the beatmaps passed on the command line into charts named after them and loads the
+/// last one which imported
+pub fn import_from_args(mut chart_load_events: EventWriter<ChartLoadEvent>) {
+    let mut imported = import_args(std::env::args())
+        .into_iter()
+        .filter_map(|osu| {
+            let chart_id = osu.file_stem()?.to_string_lossy().into_owned();
+
+            import(&osu, &chart_dir(&chart_id))
+                .map_err(|error| error!("Could not import {}: {error}", osu.display()))
+                .ok()
+                .map(|_| chart_id)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(chart_id) = imported.pop() {
+        chart_load_events.send(ChartLoadEvent {
+            chart_id,
+            start_from: r64(0.),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SAMPLE: &str = include_str!("fixtures/sample.osu");
+
+    fn prompt_times(chart: &Chart) -> Vec<(f32, f32)> {
+        chart
+            .prompts
+            .iter()
+            .map(|prompt| match prompt.press_kind {
+                PressKind::Press(at) => (at.raw(), at.raw()),
+                PressKind::Hold(start, end) => (start.raw(), end.raw()),
+            })
+            .collect()
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn parse_beatmap() {
+        let Beatmap { audio, chart } = parse(SAMPLE).unwrap();
+
+        assert_eq!(audio, PathBuf::from("audio.ogg"));
+        assert_eq!(chart.meta, ChartMeta {
+            title: "Sample".into(),
+            artist: "Someone".into(),
+            version: "Normal".into(),
+            ..default()
+        });
+
+        // Inherited points only change slider velocity
+        assert_eq!(*chart.tempo, [
+            Tempo { offset: p32(1.), bpm: p32(120.), meter: 4 },
+            Tempo { offset: p32(5.), bpm: p32(240.), meter: 3 },
+        ]);
+
+        let expected = [
+            (1., 1.),
+            (2., 2.5),
+            (2.2, 2.45),
+            // Doubled velocity with a repeat
+            (3., 3.5),
+            (5., 5. + 157. / 140. * 0.25),
+            (6., 6. + 300. / 140. * 0.25),
+            (7., 8.),
+        ];
+
+        prompt_times(&chart).into_iter().zip(expected).for_each(|(times, expected)| {
+            assert!((times.0 - expected.0).abs() < 0.0001, "{times:?} {expected:?}");
+            assert!((times.1 - expected.1).abs() < 0.0001, "{times:?} {expected:?}");
+        });
+        assert_eq!(chart.prompts.len(), expected.len());
+
+        // Overlapping sliders are put on separate channels
+        assert_eq!(
+            chart.clips.iter().map(|clip| clip.coverage.clone()).collect::<Vec<_>>(),
+            [0, 1, 0, 0, 0].map(|channel| vec![CoverageRange::new(channel, channel)])
+        );
+
+        // Sliders are drawn by clouds following the spline of their channel while they play
+        assert_eq!(
+            chart.clouds.iter().map(|record| record.activations[0].0.clone()).collect::<Vec<_>>(),
+            chart.clips.iter().map(|clip| clip.offsets.clone()).collect::<Vec<_>>()
+        );
+        assert!(chart.clouds.iter().map(|record| record.cloud.routes().next().unwrap().1).eq([[0], [1], [0], [0], [0]]));
+    }
+
+    #[test]
+    fn import_flags() {
+        let args = [
+            "rhythm-engine",
+            "--import-osu",
+            "a.osu",
+            "--import-osu",
+            "b/c.osu",
+            "d.osu",
+        ];
+        assert_eq!(
+            import_args(args.map(String::from)),
+            [PathBuf::from("a.osu"), PathBuf::from("b/c.osu")]
+        );
+        assert!(import_args(["--import-osu".to_string()]).is_empty());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn slider_paths() {
+        let Beatmap { chart, .. } = parse(SAMPLE).unwrap();
+        let end = |index: usize| chart.sequences.splines[index][0].val.play(t32(1.));
+        let slide = |index: usize| match &chart.automations[index] {
+            AutomationSource::Anchors { automation, .. } => automation,
+            AutomationSource::Audio(_) => panic!("Expected anchors"),
+        };
+
+        // Linear with y flipped
+        assert!(end(0).distance(Vec2::new(140., 0.)) < 0.001);
+        assert!(end(1).distance(Vec2::new(0., -70.)) < 0.001);
+
+        // Slides go back and forth across the path
+        assert_eq!(
+            slide(2).iter().map(|anchor| (anchor.x.raw(), anchor.val.raw())).collect::<Vec<_>>(),
+            [(0., 0.), (0.25, 1.), (0.5, 0.)]
+        );
+
+        // Perfect circle which is clipped to the slider length
+        assert!(matches!(chart.sequences.splines[3][0].val.path[..], [Segment { curvature: Curvature::Circular(_), .. }]));
+        assert!(end(3).distance(Vec2::new(100., 0.)) < 0.001);
+        assert!((slide(3)[1].val.raw() - 157. / (50. * std::f32::consts::PI)).abs() < 0.0001);
+
+        // Bezier split at the repeated point and extended to the slider length
+        let bezier = &chart.sequences.splines[4][0].val;
+        assert!(matches!(bezier.path[..], [
+            Segment { curvature: Curvature::Linear, .. },
+            Segment { curvature: Curvature::Quadratic(_), .. },
+            Segment { curvature: Curvature::Linear, .. },
+        ]));
+        assert!((bezier.lut.last().unwrap().quantify().raw() - 300.).abs() < 0.01);
+        assert_eq!(slide(4)[1].val, t32(1.));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let Err(ImportError::Parse { line, .. }) = parse("[HitObjects]\n\n1,2,3,1,0\n1,2,x,1,0")
+        else {
+            panic!("Expected a parse error")
+        };
+        assert_eq!(line, 4);
+
+        assert!(matches!(
+            parse("[HitObjects]\n1,2,3,2,0,X|4:5,1,10"),
+            Err(ImportError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn import_chart() {
+        let root = std::env::temp_dir().join(format!("osu-import-{}", std::process::id()));
+        let dir = root.join("chart");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("sample.osu"), SAMPLE).unwrap();
+        fs::write(root.join("audio.ogg"), b"OggS").unwrap();
+
+        import(&root.join("sample.osu"), &dir).unwrap();
+
+        assert_eq!(fs::read(dir.join("audio.ogg")).unwrap(), b"OggS");
+        let chart = Chart::load(&dir).unwrap();
+        assert_eq!(chart.meta.audio, "audio.ogg");
+        assert_eq!(chart.prompts, parse(SAMPLE).unwrap().chart.prompts);
+        assert_eq!(chart.clips.len(), 5);
+        assert!(chart.sequences.splines[0][0].val.lut.len() > 1);
+
+        fs::write(
+            root.join("mp3.osu"),
+            SAMPLE.replace("audio.ogg", "audio.mp3"),
+        )
+        .unwrap();
+        fs::write(root.join("audio.mp3"), b"ID3").unwrap();
+        import(&root.join("mp3.osu"), &root.join("mp3")).unwrap();
+        assert_eq!(
+            Chart::load(&root.join("mp3")).unwrap().meta.audio,
+            "audio.mp3"
+        );
+
+        fs::write(
+            root.join("m4a.osu"),
+            SAMPLE.replace("audio.ogg", "audio.m4a"),
+        )
+        .unwrap();
+        assert!(matches!(
+            import(&root.join("m4a.osu"), &root.join("m4a")),
+            Err(ImportError::UnsupportedAudio(_))
+        ));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{
+    audio::*, automation::Weight, editor::Layout, hit::HitBindings,
+    silhouettes::LuminositySettings, GameState,
+};
+use bevy::{core_pipeline::bloom::BloomSettings, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiSettings};
+use noisy_float::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Bumped whenever a field is renamed or changes meaning. Added fields are filled in with
+/// their defaults so they don't need a new version.
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Smallest and largest UI scales
+const UI_SCALES: (f32, f32) = (0.5, 2.);
+
+/// Seconds without changes before settings are written to disk
+const SAVE_DELAY: f32 = 0.5;
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub ui_scale: f32,
+    /// Milliseconds added to the audio offset of every chart
+    pub audio_offset: f32,
+    /// Milliseconds the visuals are drawn ahead of the audio
+    pub visual_offset: f32,
+    /// Keys of the hit register slots
+    pub hit_bindings: [KeyCode; 4],
+    /// Amplitude of every sound
+    pub volume: f32,
+    pub bloom_intensity: f32,
+    pub luminosity: LuminositySettings,
+    pub editor_layout: Layout,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            ui_scale: 1.,
+            audio_offset: 0.,
+            visual_offset: 0.,
+            hit_bindings: HitBindings::default().0,
+            volume: 1.,
+            bloom_intensity: 0.3,
+            luminosity: LuminositySettings::default(),
+            editor_layout: Layout::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// `settings.ron` in the config directory of the platform
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|config| config.join("rhythm-engine").join("settings.ron"))
+    }
+
+    /// Version of the settings stored at the path even when the rest can't be read
+    fn stored_version(path: &Path) -> Option<u32> {
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(default)]
+            version: u32,
+        }
+
+        let text = fs::read_to_string(path).ok()?;
+        ron::from_str::<Stored>(&text)
+            .ok()
+            .map(|stored| stored.version)
+    }
+
+    /// Settings from a newer version are ignored rather than half understood
+    pub fn load(path: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        if let Some(version) = Self::stored_version(path).filter(|v| SETTINGS_VERSION < *v) {
+            warn!(
+                "Settings {} are from a newer version {version} and won't be overwritten",
+                path.display()
+            );
+            return Self::default();
+        }
+
+        match ron::from_str::<Self>(&text) {
+            Ok(settings) => Self {
+                version: SETTINGS_VERSION,
+                ui_scale: settings.ui_scale.clamp(UI_SCALES.0, UI_SCALES.1),
+                ..settings
+            },
+            Err(error) => {
+                warn!("Could not read settings {}: {error}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Settings from a newer version are kept so going back to an older build doesn't lose them
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(version) = Self::stored_version(path).filter(|v| SETTINGS_VERSION < *v) {
+            return Err(format!("Keeping settings from newer version {version}"));
+        }
+
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|error| error.to_string())
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut hit_bindings: ResMut<HitBindings>,
+    mut luminosity: ResMut<LuminositySettings>,
+    mut egui_settings: ResMut<EguiSettings>,
+    mut blooms: Query<&mut BloomSettings>,
+    song_channel: Res<AudioChannel<SongChannel>>,
+    metronome_channel: Res<AudioChannel<MetronomeChannel>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    egui_settings.scale_factor = settings.ui_scale as f64;
+    hit_bindings.set_if_neq(HitBindings(settings.hit_bindings));
+    luminosity.set_if_neq(settings.luminosity.clone());
+    blooms
+        .iter_mut()
+        .for_each(|mut bloom| bloom.intensity = settings.bloom_intensity);
+
+    song_channel.set_volume(settings.volume as f64);
+    metronome_channel.set_volume(settings.volume as f64);
+}
+
+/// Settings are written once they stop changing so dragging a slider doesn't write every frame
+fn persist_settings(time: Res<Time>, settings: Res<Settings>, mut changed_at: Local<Option<f32>>) {
+    if settings.is_changed() && !settings.is_added() {
+        *changed_at = Some(time.elapsed_seconds());
+    }
+
+    let Some(since) = *changed_at else {
+        return;
+    };
+
+    if SAVE_DELAY < time.elapsed_seconds() - since {
+        *changed_at = None;
+
+        if let Some(path) = Settings::path() {
+            if let Err(error) = settings.save(&path) {
+                error!("Could not save settings {}: {error}", path.display());
+            }
+        }
+    }
+}
+
+/// Whether the settings window is shown
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct SettingsScreen(pub bool);
+
+fn curve_ui(ui: &mut egui::Ui, label: &str, curve: &mut Weight, threshold: &mut R32) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        match curve {
+            Weight::Quadratic(k) | Weight::Cubic(k) => {
+                let mut value = k.raw();
+                ui.add(egui::DragValue::new(&mut value).speed(0.05).prefix("k: "));
+                *k = r32(value);
+            }
+            curve => {
+                ui.label(format!("{curve:?}"));
+            }
+        }
+
+        let mut value = threshold.raw();
+        ui.add(
+            egui::DragValue::new(&mut value)
+                .speed(0.05)
+                .prefix("threshold: "),
+        );
+        *threshold = r32(value);
+    });
+}
+
+/// Edits a copy of the settings so they're only marked changed when something was edited
+fn settings_screen(
+    keys: Res<Input<KeyCode>>,
+    mut screen: ResMut<SettingsScreen>,
+    mut settings: ResMut<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut rebinding: Local<Option<usize>>,
+    mut contexts: EguiContexts,
+) {
+    if !**screen {
+        *rebinding = None;
+        return;
+    }
+
+    let mut edited = settings.clone();
+
+    if let Some((slot, key)) = rebinding.zip(keys.get_just_pressed().next()) {
+        edited.hit_bindings[slot] = *key;
+        *rebinding = None;
+    }
+
+    egui::Window::new("Settings")
+        .open(&mut screen.0)
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                ui.label("UI scale");
+                ui.add(egui::Slider::new(
+                    &mut edited.ui_scale,
+                    UI_SCALES.0..=UI_SCALES.1,
+                ));
+                ui.end_row();
+
+                ui.label("Volume");
+                ui.add(egui::Slider::new(&mut edited.volume, 0.0..=1.));
+                ui.end_row();
+
+                ui.label("Audio offset");
+                ui.add(egui::DragValue::new(&mut edited.audio_offset).suffix("ms"));
+                ui.end_row();
+
+                ui.label("Visual offset");
+                ui.add(egui::DragValue::new(&mut edited.visual_offset).suffix("ms"));
+                ui.end_row();
+
+                ui.label("Bloom");
+                ui.add(egui::Slider::new(&mut edited.bloom_intensity, 0.0..=1.));
+                ui.end_row();
+
+                ui.label("Hit keys");
+                ui.horizontal(|ui| {
+                    edited
+                        .hit_bindings
+                        .iter()
+                        .enumerate()
+                        .for_each(|(slot, key)| {
+                            let text = match *rebinding == Some(slot) {
+                                true => "...".to_string(),
+                                false => format!("{key:?}"),
+                            };
+
+                            if ui.button(text).on_hover_text("Click to rebind").clicked() {
+                                *rebinding = Some(slot);
+                            }
+                        });
+                });
+                ui.end_row();
+            });
+
+            ui.separator();
+
+            let luminosity = &mut edited.luminosity;
+            curve_ui(
+                ui,
+                "Vividness",
+                &mut luminosity.vividness_curve,
+                &mut luminosity.vividness_threshold,
+            );
+            curve_ui(
+                ui,
+                "Brightness",
+                &mut luminosity.brightness_curve,
+                &mut luminosity.brightness_threshold,
+            );
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    edited = Settings::default();
+                }
+
+                if ui.button("Calibrate").clicked() {
+                    next_state.set(GameState::Calibrate);
+                }
+            });
+        });
+
+    settings.set_if_neq(edited);
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, game: &mut App) {
+        game.insert_resource(
+            Settings::path().map_or_else(Settings::default, |path| Settings::load(&path)),
+        )
+        .init_resource::<SettingsScreen>()
+        .add_systems((apply_settings, persist_settings, settings_screen));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn settings_versions() {
+        let dir = env::temp_dir().join(format!("rhythm-engine-settings-{}", std::process::id()));
+        let path = dir.join("settings.ron");
+
+        assert_eq!(Settings::load(&path), Settings::default());
+
+        let settings = Settings {
+            audio_offset: 12.,
+            hit_bindings: [KeyCode::A, KeyCode::S, KeyCode::K, KeyCode::L],
+            bloom_intensity: 0.5,
+            ..default()
+        };
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), settings);
+
+        fs::write(&path, "(version: 0, volume: 0.5)").unwrap();
+        assert_eq!(
+            Settings::load(&path),
+            Settings {
+                volume: 0.5,
+                ..default()
+            }
+        );
+
+        // Newer settings may not parse and are never overwritten
+        let newer = format!(
+            "(version: {}, volume: \"loud\", future: [1])",
+            SETTINGS_VERSION + 1
+        );
+        fs::write(&path, &newer).unwrap();
+        assert_eq!(Settings::load(&path), Settings::default());
+        assert!(Settings::default().save(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,7 +2,7 @@ use core::iter::once as iter_once;
 
 use crate::{
     audio::SongInfo,
-    automation::{sequence::*, *},
+    automation::{sequence::*, spline, *},
     harmonizer::arranger::{ChannelCoverage, CoverageRange},
     harmonizer::*,
     hit::*,
@@ -29,6 +29,7 @@ use lyon::{
 use educe::*;
 use itertools::Itertools;
 use noisy_float::{prelude::*, NoisyFloat};
+use serde::{Deserialize, Serialize};
 use tap::{Conv, Pipe, Tap};
 
 type VertexID = usize;
@@ -37,7 +38,7 @@ type TuningID = usize;
 
 #[derive(Educe)]
 #[educe(PartialEq, Ord, Eq, PartialOrd)]
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Group {
     label: String,
     #[educe(PartialEq(ignore), Ord(ignore), Eq(ignore), PartialOrd(ignore))]
@@ -46,7 +47,7 @@ struct Group {
 
 #[derive(Educe)]
 #[educe(PartialEq, Ord, Eq, PartialOrd)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 enum Tuning {
     #[educe(Ord(rank = 0))]
     Scale {
@@ -63,6 +64,8 @@ enum Tuning {
         angle: R32,
         dilation: R32,
         flip: bool,
+        /// Rotate the group about its centroid to face along the path
+        orient: bool,
     },
     #[educe(Ord(rank = 3))]
     Warp { target: GroupID },
@@ -70,18 +73,20 @@ enum Tuning {
     NA,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Route {
     target_groups: Vec<(GroupID, Vec<TuningID>)>,
     tunings: Vec<Tuning>,
     channels: Vec<u8>,
 }
 
-#[derive(Component)]
+#[derive(Clone, Component, Serialize, Deserialize)]
 pub struct PointCloud {
     points: Vec<Vec2>,
     groups: Vec<Group>,
     routes: Vec<Route>,
+    /// Activations are stored alongside their clouds in charts
+    #[serde(skip)]
     children: Vec<Entity>,
 }
 
@@ -105,30 +110,152 @@ impl InertPoint {
 #[derive(Deref, DerefMut, Component, Default, Debug)]
 pub struct ModulationCache(Vec<InertPoint>);
 
-enum Silhouette {
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Silhouette {
     Polygon,
     Curves {
         // TODO
     },
 }
 
-enum Property {
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Property {
     NA,
     Prompt { prompts: Vec<HitPrompt> },
     Repeat { step: usize, take: usize },
 }
 
-#[derive(Component)]
+#[derive(Clone, Component, Serialize, Deserialize)]
 pub struct Activation {
-    z: R32,
+    pub z: R32,
     ctrl: VertexID,
     group: GroupID,
-    base_color: [R32; 4],
-    silhouette: Silhouette,
-    property: Property,
+    pub base_color: [R32; 4],
+    pub silhouette: Silhouette,
+    pub property: Property,
+    #[serde(skip, default = "placeholder")]
     parent: Entity,
 }
 
+fn placeholder() -> Entity {
+    Entity::PLACEHOLDER
+}
+
+impl Activation {
+    /// Cloud which is drawn by the activation
+    pub fn parent(&self) -> Entity {
+        self.parent
+    }
+}
+
+impl PointCloud {
+    /// Labels of the groups targeted by each route with the channels routed to them
+    pub fn routes(&self) -> impl Iterator<Item = (Vec<&str>, &[u8])> + '_ {
+        self.routes.iter().map(|route| {
+            let labels = route
+                .target_groups
+                .iter()
+                .flat_map(|(group, _)| self.groups.get(*group))
+                .map(|group| group.label.as_str())
+                .collect();
+
+            (labels, route.channels.as_slice())
+        })
+    }
+}
+
+/// Point cloud along with its activations as stored in charts
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CloudRecord {
+    pub cloud: PointCloud,
+    pub activations: Vec<(TemporalOffsets, Activation)>,
+}
+
+impl CloudRecord {
+    #[rustfmt::skip]
+    pub fn spawn(self, world: &mut World) -> Entity {
+        let cloud = world.spawn(ModulationCache::default()).id();
+
+        let children = self
+            .activations
+            .into_iter()
+            .map(|(offsets, activation)| world
+                .spawn((offsets, Activation { parent: cloud, ..activation }))
+                .id()
+            )
+            .collect();
+
+        world.entity_mut(cloud).insert(PointCloud { children, ..self.cloud });
+        cloud
+    }
+
+    /// Clouds sorted by their first activation then by their text with their activations in
+    /// the order they were added
+    #[rustfmt::skip]
+    pub fn extract(world: &mut World) -> Vec<Self> {
+        world
+            .query::<&PointCloud>()
+            .iter(world)
+            .map(|cloud| CloudRecord {
+                cloud: cloud.clone(),
+                activations: cloud
+                    .children
+                    .iter()
+                    .flat_map(|child| world.get::<TemporalOffsets>(*child).zip(world.get::<Activation>(*child)))
+                    .map(|(offsets, activation)| (offsets.clone(), activation.clone()))
+                    .collect(),
+            })
+            .collect::<Vec<_>>()
+            .tap_mut(|records| records.sort_by_cached_key(|record| (
+                record.activations.first().map(|(offsets, _)| (offsets.start, offsets.duration)),
+                ron::to_string(record).unwrap_or_default(),
+            )))
+    }
+
+    /// Polygon which follows the spline of a channel while the activation plays.
+    /// Spline units are scaled by the dilation.
+    pub fn polygon(
+        points: Vec<Vec2>,
+        channel: u8,
+        dilation: R32,
+        offsets: TemporalOffsets,
+    ) -> Self {
+        let group = Group {
+            label: "Body".into(),
+            vertices: (0..points.len()).collect::<Vec<_>>().into(),
+        };
+        let route = Route {
+            target_groups: vec![(0, vec![0])],
+            tunings: vec![Tuning::Translation {
+                angle: r32(0.),
+                dilation,
+                flip: false,
+                orient: false,
+            }],
+            channels: vec![channel],
+        };
+        let activation = Activation {
+            z: r32(0.),
+            ctrl: 0,
+            group: 0,
+            base_color: [r32(1.); 4],
+            silhouette: Silhouette::Polygon,
+            property: Property::NA,
+            parent: Entity::PLACEHOLDER,
+        };
+
+        Self {
+            cloud: PointCloud {
+                points,
+                groups: vec![group],
+                routes: vec![route],
+                children: vec![],
+            },
+            activations: vec![(offsets, activation)],
+        }
+    }
+}
+
 #[rustfmt::skip]
 fn modulate(
     song_info: Res<SongInfo>,
@@ -139,7 +266,7 @@ fn modulate(
     let joined = clouds.iter_mut().filter(|(cloud, _)| cloud.children
         .iter()
         .flat_map(|entity| activations.get(*entity).ok())
-        .any(|offsets| offsets.playable_at(song_info.pos))
+        .any(|offsets| offsets.playable_at(song_info.visual_pos()))
     );
 
     joined.for_each(|(PointCloud { points, groups, routes, .. }, mut cache)| {
@@ -220,18 +347,34 @@ fn modulate(
                         cache[index].pos = cache[index].pos.scale_about(ctrl, *factor * dilation)
                     })
                 },
-                Modulation::Translation(shift) => {
-                    let (angle, dilation, flip) = match tuning {
-                        Tuning::Translation { angle, dilation, flip } => (angle, dilation, flip),
-                        _ => (r32(0.), r32(1.), false),
+                Modulation::Translation { shift, heading } => {
+                    let (angle, dilation, flip, orient) = match tuning {
+                        Tuning::Translation { angle, dilation, flip, orient } => {
+                            (angle, dilation, flip, orient)
+                        },
+                        _ => (r32(0.), r32(1.), false, false),
                     };
 
-                    let tuned_shift = shift
+                    let tune = |vec: Vec2| vec
                         .rotate_about(Vec2::default(), r32(angle.raw().to_radians()))
-                        .scale_about(Vec2::default(), dilation)
                         .tap_mut(|vec| if flip { vec.x = -vec.x });
 
-                    indices.for_each(|index| cache[index].pos += tuned_shift)
+                    let tuned_shift = tune(*shift).scale_about(Vec2::default(), dilation);
+
+                    indices.clone().for_each(|index| cache[index].pos += tuned_shift);
+
+                    if orient {
+                        let theta = Vec2::from_angle(heading.raw().to_radians())
+                            .pipe(tune)
+                            .pipe(spline::heading)
+                            .pipe(|theta| r32(theta.raw().to_radians()));
+
+                        let ctrl = indices.clone().map(|i| cache[i].pos).centroid();
+
+                        indices.for_each(|i| {
+                            cache[i].pos = cache[i].pos.rotate_about(ctrl, theta)
+                        })
+                    }
                 },
                 Modulation::Invalid => {}
             }
@@ -239,12 +382,12 @@ fn modulate(
     });
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LuminositySettings {
-    vividness_curve: Weight,
-    vividness_threshold: R32,
-    brightness_curve: Weight,
-    brightness_threshold: R32,
+    pub vividness_curve: Weight,
+    pub vividness_threshold: R32,
+    pub brightness_curve: Weight,
+    pub brightness_threshold: R32,
 }
 
 impl Default for LuminositySettings {
@@ -284,7 +427,7 @@ pub fn render(
 
     activations
         .iter()
-        .filter(|(_, offsets, ..)| offsets.playable_at(song_info.pos))
+        .filter(|(_, offsets, ..)| offsets.playable_at(song_info.visual_pos()))
         .flat_map(|(entity, offsets, activation)| clouds
             .get(activation.parent)
             .map(|parent| (entity, offsets, activation, parent))
@@ -500,3 +643,89 @@ pub mod debug {
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    fn translate_bar(tuning: Tuning, heading: f32) -> Vec<Vec2> {
+        let mut game = App::new();
+        game.add_system(modulate);
+
+        let cloud = game.world.spawn(ModulationCache::default()).id();
+        let activation = game.world.spawn((
+            TemporalOffsets { start: p32(0.), duration: p32(1000.) },
+            Activation {
+                z: r32(0.),
+                ctrl: 0,
+                group: 0,
+                base_color: [1., 1., 1., 1.].map(r32),
+                silhouette: Silhouette::Polygon,
+                property: Property::NA,
+                parent: cloud,
+            },
+        ))
+        .id();
+
+        game.world.entity_mut(cloud).insert(PointCloud {
+            children: vec![activation],
+            points: vec![Vec2::new(-2., 0.), Vec2::new(2., 0.)],
+            groups: vec![Group { label: String::from("bar"), vertices: vec![0, 1].into() }],
+            routes: vec![Route {
+                target_groups: vec![(0, vec![0])],
+                tunings: vec![tuning],
+                channels: vec![0],
+            }],
+        });
+
+        game.insert_resource(SongInfo::default())
+            .insert_resource(Table::<Option<Modulation>>::default().tap_mut(|table| {
+                table[0] = Some(Modulation::Translation {
+                    shift: Vec2::new(10., 0.),
+                    heading: r32(heading),
+                })
+            }));
+
+        game.update();
+
+        game.world
+            .query::<&ModulationCache>()
+            .single(&game.world)
+            .iter()
+            .map(|point| point.pos)
+            .collect()
+    }
+
+    fn translation(angle: f32, orient: bool) -> Tuning {
+        Tuning::Translation {
+            angle: r32(angle),
+            dilation: r32(1.),
+            flip: false,
+            orient,
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn translation_orients_to_path() {
+        let cases = [
+            (translation(0., false), 90., [(8., 0.), (12., 0.)]),
+            (translation(0., true), 0., [(8., 0.), (12., 0.)]),
+            (translation(0., true), 90., [(10., -2.), (10., 2.)]),
+            (translation(0., true), 180., [(12., 0.), (8., 0.)]),
+            // The heading is tuned along with the shift
+            (translation(90., true), 90., [(2., 10.), (-2., 10.)]),
+        ];
+
+        cases.into_iter().for_each(|(tuning, heading, expected)| {
+            let positions = translate_bar(tuning, heading);
+            positions.iter().zip(expected).for_each(|(position, (x, y))| {
+                assert!(
+                    position.distance(Vec2::new(x, y)) < 0.001,
+                    "{tuning:?} {heading}: {positions:?}"
+                )
+            })
+        })
+    }
+}
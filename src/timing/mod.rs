@@ -1,11 +1,74 @@
 use crate::{hit::*, utils::*};
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tap::Pipe;
 
 enum Timing {
     BPM(u16),
     Manual,
 }
 
+/// Tempo which holds from its offset until the next tempo change
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tempo {
+    pub offset: P32,
+    pub bpm: P32,
+    /// Beats per measure
+    pub meter: u8,
+}
+
+impl Quantify for Tempo {
+    fn quantify(&self) -> P32 {
+        self.offset
+    }
+}
+
+impl Tempo {
+    pub fn beat_length(&self) -> P32 {
+        p32(60.) / self.bpm
+    }
+}
+
+/// Tempo changes sorted by offset
+#[derive(Debug, Clone, Default, PartialEq, Deref, DerefMut, Resource, Serialize, Deserialize)]
+pub struct TempoMap(pub Vec<Tempo>);
+
+impl TempoMap {
+    /// Tempo in effect at the offset. Offsets before the first tempo use the first tempo.
+    pub fn at(&self, offset: P32) -> Option<&Tempo> {
+        self.partition_point(|tempo| tempo.offset <= offset)
+            .saturating_sub(1)
+            .pipe(|index| self.get(index))
+    }
+
+    /// Offsets of every beat within the range
+    #[rustfmt::skip]
+    pub fn beats(&self, from: P32, to: P32) -> impl Iterator<Item = P32> + '_ {
+        self.iter()
+            .enumerate()
+            .filter(|(_, tempo)| f32::EPSILON < tempo.bpm.raw())
+            .flat_map(move |(index, tempo)| {
+                let end = self.get(index + 1).map_or(to, |next| next.offset.min(to));
+                let skipped = ((from.raw() - tempo.offset.raw()) / tempo.beat_length().raw())
+                    .ceil()
+                    .max(0.);
+
+                (skipped as usize..)
+                    .map(move |beat| tempo.offset + tempo.beat_length() * beat as f32)
+                    .take_while(move |offset| *offset < end)
+            })
+            .filter(move |offset| from <= *offset)
+    }
+
+    /// Whether the beat at the offset starts a measure
+    pub fn is_downbeat(&self, offset: P32) -> bool {
+        self.at(offset).is_some_and(|tempo| {
+            let beat = ((offset.raw() - tempo.offset.raw()) / tempo.beat_length().raw()).round();
+            beat as i64 % tempo.meter.max(1) as i64 == 0
+        })
+    }
+}
+
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
 pub struct ClampedTime {
     pub offset: P32,
@@ -26,7 +89,7 @@ impl ClampedTime {
 #[derive(Clone, Copy, Default, Deref, DerefMut, PartialEq, Eq, Debug)]
 pub struct SeekTime(pub P32);
 
-#[derive(Clone, Component)]
+#[derive(Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
 pub struct TemporalOffsets {
     pub start: P32,
     pub duration: P32,
@@ -41,3 +104,37 @@ impl TemporalOffsets {
         f32::EPSILON < self.duration.raw() && self.scheduled_at(time)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[rustfmt::skip]
+    fn tempo_map_beats() {
+        let tempos = TempoMap(vec![
+            Tempo { offset: p32(1.), bpm: p32(120.), meter: 4 },
+            Tempo { offset: p32(3.), bpm: p32(60.), meter: 3 },
+        ]);
+
+        assert_eq!(
+            tempos.beats(p32(0.), p32(6.)).map(|beat| beat.raw()).collect::<Vec<_>>(),
+            [1., 1.5, 2., 2.5, 3., 4., 5.]
+        );
+        assert_eq!(
+            tempos.beats(p32(2.2), p32(4.5)).map(|beat| beat.raw()).collect::<Vec<_>>(),
+            [2.5, 3., 4.]
+        );
+
+        assert_eq!(tempos.at(p32(0.)).map(|tempo| tempo.meter), Some(4));
+        assert_eq!(tempos.at(p32(2.9)).map(|tempo| tempo.meter), Some(4));
+        assert_eq!(tempos.at(p32(3.)).map(|tempo| tempo.meter), Some(3));
+        assert_eq!(TempoMap::default().at(p32(1.)), None);
+
+        assert_eq!(
+            tempos.beats(p32(0.), p32(6.)).filter(|beat| tempos.is_downbeat(*beat)).map(|beat| beat.raw()).collect::<Vec<_>>(),
+            [1., 3.]
+        );
+    }
+}
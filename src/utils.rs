@@ -5,9 +5,15 @@ use educe::*;
 use itertools::Itertools;
 use lyon::tessellation::*;
 use noisy_float::{prelude::*, FloatChecker, NoisyFloat};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tap::{Pipe, Tap};
 
-use std::{collections::HashSet, hash::Hash, marker::PhantomData};
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct UnitIntervalChecker;
@@ -58,6 +64,12 @@ pub trait Quantify {
 pub trait Lerp {
     type Output;
     fn lerp(&self, next: &Self, t: T32) -> Self::Output;
+
+    /// Lerp which extrapolates past either end for overshooting weights.
+    /// Types which cannot represent the overshoot clamp `t` instead.
+    fn lerp_unclamped(&self, next: &Self, t: R32) -> Self::Output {
+        self.lerp(next, t32(t.raw().clamp(0., 1.)))
+    }
 }
 
 pub trait CompletionRatio {
@@ -83,6 +95,18 @@ impl<Checker: FloatChecker<f32>> Lerp for NoisyFloat<f32, Checker> {
     fn lerp(&self, next: &Self, t: T32) -> Self::Output {
         Self::new(self.raw() + (next.raw() - self.raw()) * t.raw())
     }
+
+    /// Overshoots past the bounds of the checker are held at the closest bound so the output
+    /// stays continuous
+    fn lerp_unclamped(&self, next: &Self, t: R32) -> Self::Output {
+        let value = self.raw() + (next.raw() - self.raw()) * t.raw();
+
+        [value, value.max(0.), value.clamp(0., 1.)]
+            .into_iter()
+            .filter(|_| !value.is_nan())
+            .find(|value| Checker::check(*value))
+            .map_or_else(|| self.lerp(next, t32(t.raw().clamp(0., 1.))), Self::new)
+    }
 }
 
 impl<Checker: FloatChecker<f32>> CompletionRatio for NoisyFloat<f32, Checker> {
@@ -111,20 +135,70 @@ impl Vec2Ext for Vec2 {
     }
 }
 
+/// Remembers where the last lookup in a control table landed.
+/// Playback mostly moves forward so the next lookup can gallop from there instead of searching
+/// the entire table. Any stale position is still correct, just slower.
+/// Only spline LUTs hold a cursor for now. Automations and sequences are shared between
+/// channels playing at different offsets so their lookups use a plain binary search.
+#[derive(Debug, Default)]
+pub struct Cursor(AtomicUsize);
+
+impl Clone for Cursor {
+    fn clone(&self) -> Self {
+        Self(AtomicUsize::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
 pub trait ControlTable<'a, T> {
     fn at_or_after(self, offset: P32) -> &'a [T];
+    fn seek(self, offset: P32, cursor: &Cursor) -> &'a [T];
     fn interp(self, offset: P32) -> Result<<T as Lerp>::Output, &'a T>
     where
         T: Lerp;
+    fn seek_interp(self, offset: P32, cursor: &Cursor) -> Result<<T as Lerp>::Output, &'a T>
+    where
+        T: Lerp;
+}
+
+fn lerp_found<T: Quantify + Lerp>(found: &[T], offset: P32) -> Result<<T as Lerp>::Output, &T> {
+    match found {
+        [prev, curr, ..] => offset
+            .completion_ratio(prev.quantify(), curr.quantify())
+            .pipe(|t| prev.lerp(curr, t))
+            .pipe(Ok),
+        [single] => Err(single),
+        _ => panic!("Unexpected existing no item control table"),
+    }
 }
 
 /// Must be non-empty and sorted
 impl<'a, T: Quantify> ControlTable<'a, T> for &'a [T] {
     fn at_or_after(self, offset: P32) -> &'a [T] {
-        self.iter()
-            .take_while(|item| item.quantify() < offset)
-            .count()
+        self.partition_point(|item| item.quantify() < offset)
+            .saturating_sub(1)
+            .pipe(|start| &self[start..])
+    }
+
+    #[rustfmt::skip]
+    fn seek(self, offset: P32, cursor: &Cursor) -> &'a [T] {
+        let hint = cursor.0.load(Ordering::Relaxed).min(self.len().saturating_sub(1));
+
+        let preceding = if hint == 0 || self[hint].quantify() < offset {
+            // Everything up to the hint precedes the offset. Gallop forward to bracket the rest.
+            let mut stride = 1;
+            while self.get(hint + stride).is_some_and(|item| item.quantify() < offset) {
+                stride *= 2;
+            }
+            (hint + stride + 1)
+                .min(self.len())
+                .pipe(|end| hint + self[hint..end].partition_point(|item| item.quantify() < offset))
+        } else {
+            self[..hint].partition_point(|item| item.quantify() < offset)
+        };
+
+        preceding
             .saturating_sub(1)
+            .tap(|start| cursor.0.store(*start, Ordering::Relaxed))
             .pipe(|start| &self[start..])
     }
 
@@ -132,14 +206,14 @@ impl<'a, T: Quantify> ControlTable<'a, T> for &'a [T] {
     where
         T: Lerp,
     {
-        match self.at_or_after(offset) {
-            [prev, curr, ..] => offset
-                .completion_ratio(prev.quantify(), curr.quantify())
-                .pipe(|t| prev.lerp(curr, t))
-                .pipe(Ok),
-            [single] => Err(single),
-            _ => panic!("Unexpected existing no item control table"),
-        }
+        lerp_found(self.at_or_after(offset), offset)
+    }
+
+    fn seek_interp(self, offset: P32, cursor: &Cursor) -> Result<<T as Lerp>::Output, &'a T>
+    where
+        T: Lerp,
+    {
+        lerp_found(self.seek(offset, cursor), offset)
     }
 }
 
@@ -200,10 +274,7 @@ impl<T> From<Entity> for GenID<T> {
 
 impl<T> Clone for GenID<T> {
     fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            _phantom: PhantomData,
-        }
+        *self
     }
 }
 
@@ -252,6 +323,19 @@ impl<T, P: Property<T>> Ensured<T, P> {
     }
 }
 
+/// Serialized as the inner data. The property is ensured again on deserialization.
+impl<T: Serialize, P: Property<T>> Serialize for Ensured<T, P> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>, P: Property<T>> Deserialize<'de> for Ensured<T, P> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Ensured::new)
+    }
+}
+
 impl<T, P: Property<T>> From<T> for Ensured<T, P> {
     fn from(value: T) -> Self {
         Ensured::new(value)
@@ -323,13 +407,7 @@ pub struct Realestate<T = ()> {
 
 impl<T> Clone for Realestate<T> {
     fn clone(&self) -> Self {
-        Realestate {
-            x0: self.x0,
-            x1: self.x1,
-            y0: self.y0,
-            y1: self.y1,
-            _phantom: PhantomData,
-        }
+        *self
     }
 }
 
@@ -428,3 +506,28 @@ pub fn fixed_layout_bug_workaround(ui: &mut egui::Ui) {
     ui.set_width(ui.available_width());
     ui.set_height(ui.available_height());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn seek_matches_search() {
+        let table = (0..200).map(|i| p32((i / 2) as f32)).collect::<Vec<_>>();
+        let cursor = Cursor::default();
+
+        let offsets = (0..=220)
+            .chain((0..=220).rev())
+            .chain([150, 3, 199, 0, 120, 121, 40])
+            .map(|i| p32(i as f32 / 2.));
+
+        offsets.for_each(|offset| {
+            assert_eq!(
+                table.as_slice().at_or_after(offset).len(),
+                table.as_slice().seek(offset, &cursor).len(),
+                "Offset: {offset}"
+            )
+        })
+    }
+}